@@ -1,14 +1,121 @@
 use serde::{Deserialize, Serialize};
-use std::{env, fs, path::Path};
+use std::{collections::HashMap, env, fs, path::Path};
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct TimeoutConfig {
     pub low: u32,
     pub normal: u32,
     pub critical: u32,
+    #[serde(default = "default_osd_timeout")]
+    pub osd: u32,
 }
 
+/// Protection against senders flooding the daemon. A value of 0 disables the limit.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Notifications an application can send at once before being rate limited
+    pub burst: u32,
+    /// Notifications per second an application can send once its burst is used up
+    pub rate: f64,
+    /// Restart the timeout of an identical active notification instead of showing another one
+    pub collapse_identical: bool,
+    /// Summary length in characters
+    pub max_summary_length: usize,
+    /// Body length in characters, markup included
+    pub max_body_length: usize,
+    pub max_actions: usize,
+    /// Width and height of image data in pixels
+    pub max_image_size: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            burst: 20,
+            rate: 5.0,
+            collapse_identical: true,
+            max_summary_length: 256,
+            max_body_length: 4096,
+            max_actions: 10,
+            max_image_size: 1024,
+        }
+    }
+}
+
+/// Sounds are names from the freedesktop sound theme, or paths to sound files.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundConfig {
+    pub mute: bool,
+    /// The player. The sound file is passed as the last argument
+    pub command: String,
+    pub theme: String,
+    /// Played for notifications which do not ask for a sound themselves. Empty means silence
+    pub low: String,
+    pub normal: String,
+    pub critical: String,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        SoundConfig {
+            mute: false,
+            command: String::from("paplay"),
+            theme: String::from("freedesktop"),
+            low: String::new(),
+            normal: String::new(),
+            critical: String::new(),
+        }
+    }
+}
+
+/// Commands run through `sh -c` on notification events. Empty means no hook.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub notify: String,
+    pub close: String,
+    pub action: String,
+    pub reply: String,
+    /// Seconds after which a hook is killed
+    pub timeout: u32,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        HooksConfig {
+            notify: String::new(),
+            close: String::new(),
+            action: String::new(),
+            reply: String::new(),
+            timeout: 10,
+        }
+    }
+}
+
+/// An executable which can change or drop incoming notifications, see `filters.rs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterConfig {
+    /// Run through `sh -c`
+    pub command: String,
+    /// Milliseconds the filter has to answer
+    #[serde(default = "default_filter_timeout")]
+    pub timeout: u64,
+    /// Keep the notification unchanged if the filter fails, rather than dropping it
+    #[serde(default = "default_fail_open")]
+    pub fail_open: bool,
+}
+
+fn default_filter_timeout() -> u64 {
+    1000
+}
+
+fn default_fail_open() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum NotificationWindow {
     Single(String),
@@ -20,10 +127,104 @@ impl Default for NotificationWindow {
         NotificationWindow::Single(String::from("notification-frame"))
     }
 }
+
+impl NotificationWindow {
+    pub fn names(&self) -> &[String] {
+        match self {
+            NotificationWindow::Single(window) => std::slice::from_ref(window),
+            NotificationWindow::Multiple(windows) => windows,
+        }
+    }
+}
+
+/// Windows showing a set of notifications, rendered into a variable of their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationTarget {
+    pub window: NotificationWindow,
+    pub var: String,
+    /// Overrides eww_notification_widget
+    pub widget: Option<String>,
+}
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub app_name: Option<String>,
+    pub summary: Option<String>,
+    pub body: Option<String>,
+    pub category: Option<String>,
+    pub urgency: Option<String>,
+    pub regex: bool,
+    pub set_urgency: Option<String>,
+    pub timeout: Option<u32>,
+    pub window: Option<NotificationWindow>,
+    /// The variable rendered into, in case `window` does not read eww_notification_var
+    pub var: Option<String>,
+    pub widget: Option<String>,
+    pub skip_history: bool,
+    pub transient: Option<bool>,
+    pub drop: bool,
+}
+
+/// A daily time range during which the daemon behaves as if Do Not Disturb were on.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuietHours {
+    /// Weekdays the range starts on, like "mon" or "friday". Empty means every day
+    pub days: Vec<String>,
+    /// "HH:MM" in local time
+    pub start: String,
+    /// "HH:MM" in local time. An end before the start means the range runs past midnight
+    pub end: String,
+}
+
 fn default_icon_size() -> u32 {
     64
 }
 
+fn default_osd_timeout() -> u32 {
+    2
+}
+
+fn default_osd_window() -> String {
+    String::from("osd-frame")
+}
+
+fn default_osd_widget() -> String {
+    String::from("end-osd")
+}
+
+fn default_osd_var() -> String {
+    String::from("end-osd")
+}
+
+fn default_status_var() -> String {
+    String::from("end-status")
+}
+
+fn default_snooze_presets() -> Vec<String> {
+    vec![String::from("10m"), String::from("1h")]
+}
+
+fn default_link_command() -> String {
+    String::from("xdg-open")
+}
+
+fn default_overflow_widget() -> String {
+    String::from("end-overflow")
+}
+
+fn default_focused_monitor() -> String {
+    String::from("all")
+}
+
+fn default_group_widget() -> String {
+    String::from("end-group")
+}
+
+fn default_history_group_widget() -> String {
+    String::from("end-history-group")
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub eww_binary_path: String,
@@ -34,6 +235,22 @@ pub struct Config {
     pub eww_notification_window: NotificationWindow,
     pub eww_notification_widget: String,
     pub eww_notification_var: String,
+    /// Windows and variables for "low", "normal" or "critical" notifications, instead of
+    /// eww_notification_window and eww_notification_var
+    #[serde(default)]
+    pub eww_urgency_targets: HashMap<String, NotificationTarget>,
+    /// "all", "hyprland", "sway" or "command", see `monitor.rs`
+    #[serde(default = "default_focused_monitor")]
+    pub focused_monitor: String,
+    /// Asked for the focused output when `focused_monitor` is "command"
+    #[serde(default)]
+    pub focused_monitor_command: String,
+    /// The compositor IPC socket, instead of the one found through the environment
+    #[serde(default)]
+    pub focused_monitor_socket: String,
+    /// Output names mapped to the windows showing popups on them
+    #[serde(default)]
+    pub monitor_windows: HashMap<String, String>,
     pub eww_history_window: String,
     pub eww_history_widget: String,
     pub eww_history_var: String,
@@ -41,11 +258,47 @@ pub struct Config {
     pub eww_reply_widget: String,
     pub eww_reply_var: String,
     pub eww_reply_text: String,
+    #[serde(default = "default_status_var")]
+    pub eww_status_var: String,
+    #[serde(default = "default_osd_window")]
+    pub eww_osd_window: String,
+    #[serde(default = "default_osd_widget")]
+    pub eww_osd_widget: String,
+    #[serde(default = "default_osd_var")]
+    pub eww_osd_var: String,
     pub max_notifications: u32,
     pub notification_orientation: String,
     pub timeout: TimeoutConfig,
     #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub sound: SoundConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub filters: Vec<FilterConfig>,
+    #[serde(default)]
     pub update_history: bool,
+    #[serde(default)]
+    pub dnd_allowed_apps: Vec<String>,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub quiet_hours: Vec<QuietHours>,
+    #[serde(default = "default_snooze_presets")]
+    pub snooze_presets: Vec<String>,
+    #[serde(default = "default_link_command")]
+    pub link_command: String,
+    #[serde(default)]
+    pub max_visible_notifications: u32,
+    #[serde(default = "default_overflow_widget")]
+    pub eww_overflow_widget: String,
+    #[serde(default)]
+    pub group_notifications: bool,
+    #[serde(default = "default_group_widget")]
+    pub eww_group_widget: String,
+    #[serde(default = "default_history_group_widget")]
+    pub eww_history_group_widget: String,
 }
 
 impl Default for Config {
@@ -61,6 +314,11 @@ impl Default for Config {
             eww_notification_window: NotificationWindow::default(),
             eww_notification_widget: String::from("end-notification"),
             eww_notification_var: String::from("end-notifications"),
+            eww_urgency_targets: HashMap::new(),
+            focused_monitor: default_focused_monitor(),
+            focused_monitor_command: String::new(),
+            focused_monitor_socket: String::new(),
+            monitor_windows: HashMap::new(),
             eww_history_window: String::from("history-frame"),
             eww_history_widget: String::from("end-history"),
             eww_history_var: String::from("end-histories"),
@@ -68,14 +326,33 @@ impl Default for Config {
             eww_reply_widget: String::from("end-reply"),
             eww_reply_var: String::from("end-replies"),
             eww_reply_text: String::from("end-reply-text"),
+            eww_status_var: default_status_var(),
+            eww_osd_window: default_osd_window(),
+            eww_osd_widget: default_osd_widget(),
+            eww_osd_var: default_osd_var(),
             max_notifications: 10,
             notification_orientation: String::from("v"),
             timeout: TimeoutConfig {
                 low: 5,
                 normal: 10,
                 critical: 0,
+                osd: default_osd_timeout(),
             },
+            limits: LimitsConfig::default(),
+            sound: SoundConfig::default(),
+            hooks: HooksConfig::default(),
+            filters: vec![],
             update_history: false,
+            dnd_allowed_apps: vec![],
+            rules: vec![],
+            quiet_hours: vec![],
+            snooze_presets: default_snooze_presets(),
+            link_command: default_link_command(),
+            max_visible_notifications: 0,
+            eww_overflow_widget: default_overflow_widget(),
+            group_notifications: false,
+            eww_group_widget: default_group_widget(),
+            eww_history_group_widget: default_history_group_widget(),
         }
     }
 }
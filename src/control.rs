@@ -0,0 +1,287 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use tokio::sync::{mpsc, oneshot, watch};
+use zbus::fdo::Result;
+use zbus::interface;
+use zbus::names::InterfaceName;
+use zbus::object_server::SignalEmitter;
+use zvariant::Value;
+
+use crate::log;
+use crate::notifdaemon::{DaemonCommand, ListedNotification};
+use crate::socktools::DaemonActions;
+
+pub const CONTROL_NAME: &str = "org.end_rs.Control";
+pub const CONTROL_PATH: &str = "/org/end_rs/Control";
+
+/// The values behind the properties of the control interface, published by the state task.
+#[derive(Clone, Default, PartialEq)]
+pub struct ControlStatus {
+    pub dnd: bool,
+    pub dnd_missed: u32,
+    pub quiet_hours: bool,
+    pub active: u32,
+    pub queued: u32,
+    pub snoozed: u32,
+    pub unread: u32,
+    pub history: u32,
+}
+
+impl ControlStatus {
+    fn properties(&self) -> [(&'static str, Value<'static>); 8] {
+        [
+            ("DoNotDisturb", Value::from(self.dnd)),
+            ("DndMissed", Value::from(self.dnd_missed)),
+            ("QuietHours", Value::from(self.quiet_hours)),
+            ("Active", Value::from(self.active)),
+            ("Queued", Value::from(self.queued)),
+            ("Snoozed", Value::from(self.snoozed)),
+            ("Unread", Value::from(self.unread)),
+            ("History", Value::from(self.history)),
+        ]
+    }
+}
+
+/// Publishes a new status and emits PropertiesChanged for whatever is different.
+pub fn publish_status(
+    connection: &zbus::Connection,
+    sender: &watch::Sender<ControlStatus>,
+    status: ControlStatus,
+) {
+    let old = sender.borrow().clone();
+    if old == status {
+        return;
+    }
+    let changed: HashMap<&'static str, Value<'static>> = status
+        .properties()
+        .into_iter()
+        .zip(old.properties())
+        .filter(|((_, new), (_, old))| new != old)
+        .map(|(new, _)| new)
+        .collect();
+    sender.send_replace(status);
+
+    let connection = connection.clone();
+    tokio::spawn(async move {
+        let res = match SignalEmitter::new(&connection, CONTROL_PATH) {
+            Ok(emitter) => {
+                zbus::fdo::Properties::properties_changed(
+                    &emitter,
+                    InterfaceName::from_static_str_unchecked(CONTROL_NAME),
+                    changed,
+                    Cow::Borrowed(&[]),
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            log!("Failed to emit PropertiesChanged: {}", e);
+        }
+    });
+}
+
+/// The org.end_rs.Control interface. It offers everything the socket commands do, for tools
+/// which would rather use D-Bus.
+pub struct ControlInterface {
+    pub tx: mpsc::Sender<DaemonCommand>,
+    pub status: watch::Receiver<ControlStatus>,
+}
+
+impl ControlInterface {
+    async fn send(&self, command: DaemonCommand) -> Result<()> {
+        self.tx
+            .send(command)
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon state went away".to_string()))
+    }
+
+    /// Runs an action and returns its response.
+    async fn query(&self, action: DaemonActions) -> Result<String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(DaemonCommand::Action(action, reply_tx)).await?;
+        reply_rx
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon state went away".to_string()))
+    }
+
+    /// Runs an action whose only response is an error message.
+    async fn run(&self, action: DaemonActions) -> Result<()> {
+        let response = self.query(action).await?;
+        if response.is_empty() {
+            Ok(())
+        } else {
+            Err(zbus::fdo::Error::Failed(response))
+        }
+    }
+}
+
+#[interface(name = "org.end_rs.Control")]
+impl ControlInterface {
+    /// Active and snoozed notifications as (id, app name, summary, body, urgency, state), the
+    /// state being "shown", "queued" or "snoozed".
+    async fn list(&self) -> Result<Vec<ListedNotification>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(DaemonCommand::List(reply_tx)).await?;
+        reply_rx
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon state went away".to_string()))
+    }
+
+    async fn status(&self) -> Result<String> {
+        self.query(DaemonActions::Status).await
+    }
+
+    async fn close_notification(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::CloseNotification(id)).await
+    }
+
+    async fn invoke_action(&self, id: u32, action: String) -> Result<()> {
+        self.run(DaemonActions::ActionInvoked(id, action)).await
+    }
+
+    /// Invokes the action at `index` of the actions a widget shows for the notification.
+    async fn invoke_action_at(&self, id: u32, index: u32) -> Result<()> {
+        self.run(DaemonActions::ActionAt(id, index as usize)).await
+    }
+
+    async fn invoke_default(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::InvokeDefault(id)).await
+    }
+
+    async fn send_reply(&self, id: u32, reply: String) -> Result<()> {
+        self.run(DaemonActions::ReplySend(id, reply)).await
+    }
+
+    async fn close_reply(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::ReplyClose(id)).await
+    }
+
+    /// Pauses the timeout of a notification, or of all of them for 0.
+    async fn pause(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::Pause((id != 0).then_some(id)))
+            .await
+    }
+
+    /// Resumes the timeout of a notification, or of all of them for 0.
+    async fn resume(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::Resume((id != 0).then_some(id)))
+            .await
+    }
+
+    async fn snooze(&self, id: u32, seconds: u64) -> Result<()> {
+        self.run(DaemonActions::Snooze(id, seconds)).await
+    }
+
+    async fn set_do_not_disturb(&self, enabled: bool) -> Result<()> {
+        let action = if enabled {
+            DaemonActions::DndOn
+        } else {
+            DaemonActions::DndOff
+        };
+        self.run(action).await
+    }
+
+    async fn toggle_do_not_disturb(&self) -> Result<()> {
+        self.run(DaemonActions::DndToggle).await
+    }
+
+    async fn open_history(&self) -> Result<()> {
+        self.run(DaemonActions::OpenHistory).await
+    }
+
+    async fn close_history(&self) -> Result<()> {
+        self.run(DaemonActions::CloseHistory).await
+    }
+
+    async fn toggle_history(&self) -> Result<()> {
+        self.run(DaemonActions::ToggleHistory).await
+    }
+
+    async fn clear_history(&self) -> Result<()> {
+        self.run(DaemonActions::HistoryClear).await
+    }
+
+    async fn remove_history(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::HistoryRemove(id)).await
+    }
+
+    /// Clears the history of the application of history entry `id`.
+    async fn clear_app_history(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::HistoryClearApp(id)).await
+    }
+
+    async fn invoke_history_action(&self, id: u32, action: String) -> Result<()> {
+        self.run(DaemonActions::HistoryAction(id, action)).await
+    }
+
+    async fn invoke_history_action_at(&self, id: u32, index: u32) -> Result<()> {
+        self.run(DaemonActions::HistoryActionAt(id, index as usize))
+            .await
+    }
+
+    /// Expands the popup group of the application which sent notification `id`.
+    async fn expand_group(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::GroupExpand(id)).await
+    }
+
+    async fn collapse_group(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::GroupCollapse(id)).await
+    }
+
+    async fn toggle_group(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::GroupToggle(id)).await
+    }
+
+    /// Expands the history group of the application of history entry `id`.
+    async fn expand_history_group(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::HistoryGroupExpand(id)).await
+    }
+
+    async fn collapse_history_group(&self, id: u32) -> Result<()> {
+        self.run(DaemonActions::HistoryGroupCollapse(id)).await
+    }
+
+    #[zbus(property)]
+    fn do_not_disturb(&self) -> bool {
+        self.status.borrow().dnd
+    }
+
+    #[zbus(property)]
+    fn dnd_missed(&self) -> u32 {
+        self.status.borrow().dnd_missed
+    }
+
+    #[zbus(property)]
+    fn quiet_hours(&self) -> bool {
+        self.status.borrow().quiet_hours
+    }
+
+    /// Popups on screen, not counting OSD notifications
+    #[zbus(property)]
+    fn active(&self) -> u32 {
+        self.status.borrow().active
+    }
+
+    /// Notifications waiting for a free slot
+    #[zbus(property)]
+    fn queued(&self) -> u32 {
+        self.status.borrow().queued
+    }
+
+    #[zbus(property)]
+    fn snoozed(&self) -> u32 {
+        self.status.borrow().snoozed
+    }
+
+    #[zbus(property)]
+    fn unread(&self) -> u32 {
+        self.status.borrow().unread
+    }
+
+    /// Entries in the history
+    #[zbus(property)]
+    fn history(&self) -> u32 {
+        self.status.borrow().history
+    }
+}
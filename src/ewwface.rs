@@ -1,8 +1,11 @@
-use crate::config::{Config, NotificationWindow};
+use crate::config::Config;
 use crate::log;
-use crate::notifdaemon::{HistoryNotification, Notification};
+use crate::notifdaemon::{
+    HistoryNotification, Notification, DEFAULT_ACTION, LINK_ACTION_PREFIX, SNOOZE_ACTION_PREFIX,
+};
+use crate::rules::urgency_from_str;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 // Macro replacement example:
 macro_rules! eww_val {
@@ -29,6 +32,15 @@ pub fn eww_is_window_open(cfg: &Config, window: &str) -> bool {
 }
 
 pub fn eww_open_window(cfg: &Config, window: &str) -> Result<(), std::io::Error> {
+    eww_open_window_on(cfg, window, None)
+}
+
+/// Opens a window on the given output, or on the one from its definition.
+pub fn eww_open_window_on(
+    cfg: &Config,
+    window: &str,
+    screen: Option<&str>,
+) -> Result<(), std::io::Error> {
     log!("Opening {}", window);
     if eww_is_window_open(cfg, window) {
         log!("{} is already open", window);
@@ -38,6 +50,11 @@ pub fn eww_open_window(cfg: &Config, window: &str) -> Result<(), std::io::Error>
     let mut cmd = String::new();
     cmd.push_str(&cfg.eww_binary_path);
     cmd.push_str(" open ");
+    if let Some(screen) = screen {
+        cmd.push_str("--screen ");
+        cmd.push_str(&shlex::try_quote(screen).unwrap());
+        cmd.push(' ');
+    }
     cmd.push_str(window);
     //println!("{}", cmd);
     std::process::Command::new("sh")
@@ -101,40 +118,226 @@ pub fn eww_update_value(cfg: &Config, var: &str, value: &str) {
     log!("{} updated", var);
 }
 
+/// Sets several variables with a single eww call.
+pub fn eww_update_values(cfg: &Config, values: &[(&str, String)]) {
+    let mut cmd = String::new();
+    cmd.push_str(&cfg.eww_binary_path);
+    cmd.push_str(" update");
+    for (var, value) in values {
+        log!("Updating {} with {}", var, value);
+        let value = shlex::try_quote(value).unwrap().replace('\n', "<br>");
+        cmd.push(' ');
+        cmd.push_str(var);
+        cmd.push('=');
+        cmd.push_str(&value);
+    }
+    std::process::Command::new("sh")
+        .arg("-c")
+        .arg(&cmd)
+        .spawn()
+        .expect("Failed to execute command")
+        .wait()
+        .expect("Failed to execute command");
+}
+
 pub fn quote_hexator(s: &str) -> String {
     s.replace('"', "&#34;").replace('\'', "&#39;")
 }
 
-pub fn eww_create_notifications_value(cfg: &Config, notifs: &HashMap<u32, Notification>) -> String {
+/// Applies `quote_hexator` to every string in a JSON value, including object keys.
+fn quote_hexate_json(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => serde_json::Value::String(quote_hexator(s)),
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.iter().map(quote_hexate_json).collect())
+        }
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(key, value)| (quote_hexator(key), quote_hexate_json(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn urgency_rank(urgency: &str) -> u8 {
+    urgency_from_str(urgency).unwrap_or(1)
+}
+
+/// Collects items into groups by application name, keeping the order in which each application
+/// first appears.
+fn group_by_app<'a, T>(
+    items: impl Iterator<Item = T>,
+    app_name: impl Fn(&T) -> &'a str,
+) -> Vec<(&'a str, Vec<T>)> {
+    let mut groups: Vec<(&'a str, Vec<T>)> = Vec::new();
+    for item in items {
+        let name = app_name(&item);
+        match groups.iter_mut().find(|(app, _)| *app == name) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((name, vec![item])),
+        }
+    }
+    groups
+}
+
+/// The actions on a popup: those of the application followed by its links and snooze presets.
+/// Widgets invoke them by index, since the keys come from the sender and must never reach a
+/// shell command.
+pub fn popup_actions(cfg: &Config, notif: &Notification) -> Vec<(String, String)> {
+    let links = notif
+        .links
+        .iter()
+        .enumerate()
+        .map(|(i, (_, label))| (format!("{}{}", LINK_ACTION_PREFIX, i), label.clone()));
+    // OSD notifications come and go too quickly to be snoozed
+    let snooze_presets = if notif.osd {
+        &[][..]
+    } else {
+        &cfg.snooze_presets[..]
+    };
+    let snoozes = snooze_presets.iter().map(|preset| {
+        (
+            format!("{}{}", SNOOZE_ACTION_PREFIX, preset),
+            format!("Snooze {}", preset),
+        )
+    });
+    notif
+        .actions
+        .iter()
+        .filter(|(id, _)| id != DEFAULT_ACTION)
+        .cloned()
+        .chain(links)
+        .chain(snoozes)
+        .collect()
+}
+
+/// The actions on a history entry, invoked by index like those of the popups.
+pub fn history_actions(hist: &HistoryNotification) -> Vec<&(String, String)> {
+    hist.actions
+        .iter()
+        .filter(|(id, _)| id != DEFAULT_ACTION)
+        .collect()
+}
+
+fn eww_create_notification_json(cfg: &Config, id: u32, notif: &Notification) -> String {
+    let actions: Vec<_> = popup_actions(cfg, notif)
+        .iter()
+        .enumerate()
+        .map(|(index, (id, text))| {
+            json!({"id": quote_hexator(id), "text": quote_hexator(text), "index": index})
+        })
+        .collect();
+
+    eww_val!({
+        "actions": actions,
+        "has_default": notif.actions.iter().any(|(id, _)| id == DEFAULT_ACTION),
+        "application": quote_hexator(&notif.app_name),
+        "body": quote_hexator(&notif.body),
+        "icon": quote_hexator(&notif.icon),
+        "app_icon": quote_hexator(&notif.app_icon),
+        "id": id,
+        "summary": quote_hexator(&notif.summary),
+        "urgency": quote_hexator(&notif.urgency),
+        "value": notif.value,
+        "action_icons": notif.action_icons,
+        "x": notif.position.map(|(x, _)| x),
+        "y": notif.position.map(|(_, y)| y),
+        "category": quote_hexator(&notif.category),
+        "desktop_entry": notif.desktop_entry.as_deref().map(quote_hexator),
+        "received": notif.received,
+        "sender": notif.sender,
+        "timeout": notif.expire_timeout,
+        "hints": quote_hexate_json(&serde_json::Value::Object(notif.hints.clone())),
+    })
+}
+
+fn eww_create_notification_widget(
+    cfg: &Config,
+    id: u32,
+    notif: &Notification,
+    target_widget: &str,
+) -> String {
+    let widget = notif.widget.as_deref().unwrap_or(target_widget);
+    format!(
+        "(box ({} :notification '{}'))",
+        widget,
+        eww_create_notification_json(cfg, id, notif)
+    )
+}
+
+fn eww_create_group_widget(
+    cfg: &Config,
+    app_name: &str,
+    group: &[(u32, &Notification)],
+    expanded: bool,
+) -> String {
+    // The group is sorted by id, so the last notification is the latest one
+    let (latest_id, latest) = group[group.len() - 1];
+    let urgency = group
+        .iter()
+        .map(|(_, notif)| notif.urgency.as_str())
+        .max_by_key(|urgency| urgency_rank(urgency))
+        .unwrap_or("normal");
+    let ids: Vec<u32> = group.iter().map(|(id, _)| *id).collect();
+
+    let widget_json = eww_val!({
+        "application": quote_hexator(app_name),
+        "app_icon": quote_hexator(&latest.app_icon),
+        "icon": quote_hexator(&latest.icon),
+        "summary": quote_hexator(&latest.summary),
+        "body": quote_hexator(&latest.body),
+        "urgency": quote_hexator(urgency),
+        "count": group.len(),
+        "expanded": expanded,
+        "latest_id": latest_id,
+        "ids": ids,
+    });
+    format!("(box ({} :group '{}'))", cfg.eww_group_widget, widget_json)
+}
+
+/// Renders the popups of one target. `notifs` are the visible notifications routed to it, and
+/// `queued` the number of notifications waiting for a free slot.
+pub fn eww_create_notifications_value(
+    cfg: &Config,
+    mut notifs: Vec<(u32, &Notification)>,
+    queued: usize,
+    widget: &str,
+    expanded_groups: &HashSet<String>,
+) -> String {
     let mut widgets = format!(
         "(box :space-evenly false :orientation \"{}\" ",
         cfg.notification_orientation
     );
+    notifs.sort_by_key(|(id, _)| *id);
 
-    for notif in notifs {
-        let actions: Vec<_> = notif
-            .1
-            .actions
-            .iter()
-            .map(|(id, text)| json!({"id": quote_hexator(id), "text": quote_hexator(text)}))
-            .collect();
-
-        let widget_json = eww_val!({
-            "actions": actions,
-            "application": quote_hexator(&notif.1.app_name),
-            "body": quote_hexator(&notif.1.body),
-            "icon": quote_hexator(&notif.1.icon),
-            "app_icon": quote_hexator(&notif.1.app_icon),
-            "id": &notif.0,
-            "summary": quote_hexator(&notif.1.summary),
-            "urgency": quote_hexator(&notif.1.urgency),
-        });
-
-        let widget_string = format!(
-            "(box ({} :notification '{}'))",
-            cfg.eww_notification_widget, widget_json
-        );
-        widgets.push_str(&widget_string);
+    if cfg.group_notifications {
+        let groups = group_by_app(notifs.into_iter(), |(_, notif)| notif.app_name.as_str());
+        for (app_name, group) in groups {
+            if group.len() == 1 {
+                let (id, notif) = group[0];
+                widgets.push_str(&eww_create_notification_widget(cfg, id, notif, widget));
+                continue;
+            }
+            let expanded = expanded_groups.contains(app_name);
+            widgets.push_str(&eww_create_group_widget(cfg, app_name, &group, expanded));
+            if expanded {
+                for (id, notif) in group {
+                    widgets.push_str(&eww_create_notification_widget(cfg, id, notif, widget));
+                }
+            }
+        }
+    } else {
+        for (id, notif) in notifs {
+            widgets.push_str(&eww_create_notification_widget(cfg, id, notif, widget));
+        }
+    }
+
+    if queued > 0 {
+        widgets.push_str(&format!(
+            "(box ({} :count {}))",
+            cfg.eww_overflow_widget, queued
+        ));
     }
 
     widgets.push(')');
@@ -145,69 +348,322 @@ pub fn eww_create_reply_widget(cfg: &Config, id: u32) -> String {
     format!("(box ({} :id {}))", cfg.eww_reply_widget, id)
 }
 
-pub fn eww_update_notifications(cfg: &Config, notifs: &HashMap<u32, Notification>) {
-    let widgets = eww_create_notifications_value(cfg, notifs);
-    eww_update_value(cfg, &cfg.eww_notification_var, &widgets);
-    match &cfg.eww_notification_window {
-        NotificationWindow::Single(window) => {
-            let _res = eww_open_window(cfg, window);
+/// Where a popup is shown: the windows, the variable they read and the widget to render with.
+struct Target<'a> {
+    windows: &'a [String],
+    var: &'a str,
+    widget: &'a str,
+}
+
+/// A window or variable set by a rule takes precedence over the target of the urgency. What the
+/// rule leaves out comes from the default window and variable.
+fn notification_target<'a>(cfg: &'a Config, notif: &'a Notification) -> Target<'a> {
+    let default = Target {
+        windows: cfg.eww_notification_window.names(),
+        var: &cfg.eww_notification_var,
+        widget: &cfg.eww_notification_widget,
+    };
+    if notif.window.is_some() || notif.var.is_some() {
+        return Target {
+            windows: notif
+                .window
+                .as_ref()
+                .map_or(default.windows, |window| window.names()),
+            var: notif.var.as_deref().unwrap_or(default.var),
+            ..default
+        };
+    }
+    match cfg.eww_urgency_targets.get(&notif.urgency) {
+        Some(target) => Target {
+            windows: target.window.names(),
+            var: &target.var,
+            widget: target.widget.as_deref().unwrap_or(default.widget),
+        },
+        None => default,
+    }
+}
+
+/// Every window and variable popups can end up in, starting with the default ones.
+fn popup_targets(cfg: &Config) -> (Vec<&str>, Vec<&str>) {
+    let mut windows: Vec<&str> = Vec::new();
+    let mut vars = vec![cfg.eww_notification_var.as_str()];
+    let all_windows = cfg
+        .eww_notification_window
+        .names()
+        .iter()
+        .chain(
+            cfg.eww_urgency_targets
+                .values()
+                .flat_map(|target| target.window.names()),
+        )
+        .chain(
+            cfg.rules
+                .iter()
+                .filter_map(|rule| rule.window.as_ref())
+                .flat_map(|window| window.names()),
+        );
+    for window in all_windows {
+        if !windows.contains(&window.as_str()) {
+            windows.push(window);
         }
-        NotificationWindow::Multiple(windows) => {
-            windows.iter().for_each(|window| {
-                let _res = eww_open_window(cfg, window);
-            });
+    }
+    let all_vars = cfg
+        .eww_urgency_targets
+        .values()
+        .map(|target| target.var.as_str())
+        .chain(cfg.rules.iter().filter_map(|rule| rule.var.as_deref()));
+    for var in all_vars {
+        if !vars.contains(&var) {
+            vars.push(var);
         }
     }
+    (windows, vars)
 }
 
-pub fn eww_close_notifications(cfg: &Config) {
-    match &cfg.eww_notification_window {
-        NotificationWindow::Single(window) => {
-            let _res = eww_close_window(cfg, window);
+/// The windows of a target which show popups on `screen`: the one mapped to it in
+/// `monitor_windows`, or else the first one opened on that screen. Without a screen, all of them.
+fn screen_windows<'a>(
+    cfg: &'a Config,
+    windows: &'a [String],
+    screen: Option<&'a str>,
+) -> (&'a [String], Option<&'a str>) {
+    let screen = match screen {
+        Some(screen) if !windows.is_empty() => screen,
+        _ => return (windows, None),
+    };
+    let mapped = cfg
+        .monitor_windows
+        .get(screen)
+        .and_then(|mapped| windows.iter().find(|window| *window == mapped));
+    match mapped {
+        Some(window) => (std::slice::from_ref(window), None),
+        None => (&windows[..1], Some(screen)),
+    }
+}
+
+/// Renders the popups into their variables and opens the windows showing them. With `screen`
+/// set, only the windows on that output are opened.
+pub fn eww_update_notifications(
+    cfg: &Config,
+    notifs: &HashMap<u32, Notification>,
+    expanded_groups: &HashSet<String>,
+    screen: Option<&str>,
+) {
+    let (windows, vars) = popup_targets(cfg);
+    let popups: Vec<(u32, &Notification, Target)> = notifs
+        .iter()
+        .filter(|(_, notif)| notif.visible && !notif.osd)
+        .map(|(id, notif)| (*id, notif, notification_target(cfg, notif)))
+        .collect();
+    // The queue is shared, so it is only counted in the default windows
+    let queued = notifs.values().filter(|notif| !notif.visible).count();
+
+    // Every variable is rendered, so the ones which lost their last popup are emptied too
+    let values: Vec<(&str, String)> = vars
+        .iter()
+        .map(|var| {
+            let routed: Vec<(u32, &Notification)> = popups
+                .iter()
+                .filter(|(_, _, target)| target.var == *var)
+                .map(|(id, notif, _)| (*id, *notif))
+                .collect();
+            let widget = popups
+                .iter()
+                .find(|(_, _, target)| target.var == *var)
+                .map_or(cfg.eww_notification_widget.as_str(), |(_, _, target)| {
+                    target.widget
+                });
+            let queued = if *var == cfg.eww_notification_var {
+                queued
+            } else {
+                0
+            };
+            let value =
+                eww_create_notifications_value(cfg, routed, queued, widget, expanded_groups);
+            (*var, value)
+        })
+        .collect();
+    eww_update_values(cfg, &values);
+
+    let mut used: Vec<(&str, Option<&str>)> = Vec::new();
+    let default_windows = (queued > 0).then_some(cfg.eww_notification_window.names());
+    let target_windows = popups
+        .iter()
+        .map(|(_, _, target)| target.windows)
+        .chain(default_windows);
+    for windows in target_windows {
+        let (windows, on_screen) = screen_windows(cfg, windows, screen);
+        for window in windows {
+            if !used.iter().any(|(name, _)| name == window) {
+                used.push((window, on_screen));
+            }
         }
-        NotificationWindow::Multiple(windows) => {
-            windows.iter().for_each(|window| {
-                let _res = eww_close_window(cfg, window);
-            });
+    }
+    for window in windows {
+        if let Some((_, on_screen)) = used.iter().find(|(name, _)| *name == window) {
+            let _res = eww_open_window_on(cfg, window, *on_screen);
+        } else if eww_is_window_open(cfg, window) {
+            let _res = eww_close_window(cfg, window);
         }
     }
 }
 
-pub fn eww_create_history_value(cfg: &Config, history: &[HistoryNotification]) -> String {
+/// Renders the OSD notifications into their variable and opens or closes their window.
+pub fn eww_update_osd(cfg: &Config, notifs: &HashMap<u32, Notification>) {
+    let mut osds: Vec<(u32, &Notification)> = notifs
+        .iter()
+        .filter(|(_, notif)| notif.osd)
+        .map(|(id, notif)| (*id, notif))
+        .collect();
+    osds.sort_by_key(|(id, _)| *id);
+
+    let mut widgets = format!(
+        "(box :space-evenly false :orientation \"{}\" ",
+        cfg.notification_orientation
+    );
+    for (id, notif) in &osds {
+        widgets.push_str(&format!(
+            "(box ({} :notification '{}'))",
+            cfg.eww_osd_widget,
+            eww_create_notification_json(cfg, *id, notif)
+        ));
+    }
+    widgets.push(')');
+    eww_update_value(cfg, &cfg.eww_osd_var, &widgets);
+
+    if !osds.is_empty() {
+        let _res = eww_open_window(cfg, &cfg.eww_osd_window);
+    } else if eww_is_window_open(cfg, &cfg.eww_osd_window) {
+        let _res = eww_close_window(cfg, &cfg.eww_osd_window);
+    }
+}
+
+pub fn eww_close_popups(cfg: &Config) {
+    popup_targets(cfg).0.into_iter().for_each(|window| {
+        let _res = eww_close_window(cfg, window);
+    });
+}
+
+pub fn eww_close_notifications(cfg: &Config) {
+    let _res = eww_close_window(cfg, &cfg.eww_osd_window);
+    eww_close_popups(cfg);
+}
+
+fn eww_create_history_widget(cfg: &Config, hist: &HistoryNotification) -> String {
+    // NOTE: Keeping this as a comment for future reference in case eww_val! is not working
+    // let widget_string = format!("({} :history \"{{\\\"app_name\\\":\\\"{}\\\",\\\"body\\\":\\\"{}\\\",\\\"icon\\\":\\\"{}\\\",\\\"app_icon\\\":\\\"{}\\\",\\\"summary\\\":\\\"{}\\\"}}\")", cfg.eww_history_widget, hist.app_name, hist.body, hist.icon, hist.app_icon, hist.summary);
+    // Like the popups, the JSON is single quoted, so it must not contain single quotes
+    let history_json = json!({
+        "app_name": hist.app_name,
+        "body": hist.body,
+        "icon": hist.icon,
+        "app_icon": hist.app_icon,
+        "summary": hist.summary,
+        "urgency": hist.urgency,
+        "id": hist.id,
+        "category": hist.category,
+        "desktop_entry": hist.desktop_entry,
+        "received": hist.received,
+        "sender": hist.sender,
+        "timeout": hist.timeout,
+        "hints": hist.hints,
+        "actions": history_actions(hist)
+            .iter()
+            .enumerate()
+            .map(|(index, (id, text))| json!({"id": id, "text": text, "index": index}))
+            .collect::<Vec<_>>(),
+        "has_default": hist.actions.iter().any(|(id, _)| id == DEFAULT_ACTION),
+        "read": hist.read,
+    });
+    format!(
+        "(box ({} :history '{}'))",
+        cfg.eww_history_widget,
+        quote_hexate_json(&history_json)
+    )
+}
+
+fn eww_create_history_group_widget(
+    cfg: &Config,
+    app_name: &str,
+    group: &[&HistoryNotification],
+    expanded: bool,
+) -> String {
+    // History is walked newest first, so the first entry is the latest one
+    let latest = group[0];
+    let urgency = group
+        .iter()
+        .map(|hist| hist.urgency.as_str())
+        .max_by_key(|urgency| urgency_rank(urgency))
+        .unwrap_or("normal");
+    let group_json = json!({
+        "app_name": app_name,
+        "body": latest.body,
+        "icon": latest.icon,
+        "app_icon": latest.app_icon,
+        "summary": latest.summary,
+        "urgency": urgency,
+        "count": group.len(),
+        "expanded": expanded,
+        "latest_id": latest.id,
+    });
+    format!(
+        "(box ({} :group '{}'))",
+        cfg.eww_history_group_widget,
+        quote_hexate_json(&group_json)
+    )
+}
+
+pub fn eww_create_history_value(
+    cfg: &Config,
+    history: &[HistoryNotification],
+    expanded_groups: &HashSet<String>,
+) -> String {
     let mut history_text = "(box :space-evenly false :orientation \"".to_string();
     history_text.push_str(&cfg.notification_orientation);
     history_text.push_str("\" ");
 
     let history = history.iter().rev();
 
-    for hist in history {
-        // NOTE: Keeping this as a comment for future reference in case eww_val! is not working
-        // let widget_string = format!("({} :history \"{{\\\"app_name\\\":\\\"{}\\\",\\\"body\\\":\\\"{}\\\",\\\"icon\\\":\\\"{}\\\",\\\"app_icon\\\":\\\"{}\\\",\\\"summary\\\":\\\"{}\\\"}}\")", cfg.eww_history_widget, hist.app_name, hist.body, hist.icon, hist.app_icon, hist.summary);
-        let widget_string = format!(
-            "(box ({} :history `{}`))",
-            cfg.eww_history_widget,
-            eww_val!({
-                "app_name": hist.app_name,
-                "body": hist.body,
-                "icon": hist.icon,
-                "app_icon": hist.app_icon,
-                "summary": hist.summary,
-                "urgency": hist.urgency
-            })
-        );
-        history_text.push_str(&widget_string);
+    if cfg.group_notifications {
+        for (app_name, group) in group_by_app(history, |hist| hist.app_name.as_str()) {
+            if group.len() == 1 {
+                history_text.push_str(&eww_create_history_widget(cfg, group[0]));
+                continue;
+            }
+            let expanded = expanded_groups.contains(app_name);
+            history_text.push_str(&eww_create_history_group_widget(
+                cfg, app_name, &group, expanded,
+            ));
+            if expanded {
+                for hist in group {
+                    history_text.push_str(&eww_create_history_widget(cfg, hist));
+                }
+            }
+        }
+    } else {
+        for hist in history {
+            history_text.push_str(&eww_create_history_widget(cfg, hist));
+        }
     }
     history_text.push(')');
     history_text
 }
 
-pub fn eww_update_history(cfg: &Config, history: &[HistoryNotification]) {
-    let widgets = eww_create_history_value(cfg, history);
+pub fn eww_update_history(
+    cfg: &Config,
+    history: &[HistoryNotification],
+    expanded_groups: &HashSet<String>,
+) {
+    let widgets = eww_create_history_value(cfg, history, expanded_groups);
     eww_update_value(cfg, &cfg.eww_history_var, &widgets);
 }
 
-pub fn eww_update_and_open_history(cfg: &Config, history: &[HistoryNotification]) {
-    eww_update_history(cfg, history);
+pub fn eww_update_and_open_history(
+    cfg: &Config,
+    history: &[HistoryNotification],
+    expanded_groups: &HashSet<String>,
+) {
+    eww_update_history(cfg, history, expanded_groups);
     let _res = eww_open_window(cfg, &cfg.eww_history_window);
 }
 
@@ -215,8 +671,12 @@ pub fn eww_close_history(cfg: &Config) {
     let _res = eww_close_window(cfg, &cfg.eww_history_window);
 }
 
-pub fn eww_toggle_history(cfg: &Config, history: &[HistoryNotification]) {
-    let widgets = eww_create_history_value(cfg, history);
+pub fn eww_toggle_history(
+    cfg: &Config,
+    history: &[HistoryNotification],
+    expanded_groups: &HashSet<String>,
+) {
+    let widgets = eww_create_history_value(cfg, history, expanded_groups);
     eww_update_value(cfg, &cfg.eww_history_var, &widgets);
     let _res = eww_toggle_window(cfg, &cfg.eww_history_window);
 }
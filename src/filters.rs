@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::config::FilterConfig;
+use crate::log;
+
+/// Filters must answer with the version they were given. It goes up whenever the input or the
+/// output changes incompatibly.
+pub const FILTER_PROTOCOL_VERSION: u32 = 1;
+
+/// An incoming notification as filters see it, before markup and icons are processed.
+#[derive(Serialize, Clone)]
+pub struct FilterNotification {
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// (key, label) pairs
+    pub actions: Vec<(String, String)>,
+    pub urgency: Option<String>,
+    pub category: Option<String>,
+    /// Milliseconds, -1 meaning the default of the daemon
+    pub expire_timeout: i32,
+    /// All hints except for image data. Changes to them are ignored
+    pub hints: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct FilterInput<'a> {
+    version: u32,
+    notification: &'a FilterNotification,
+}
+
+/// Fields a filter replaces. Missing fields are left as they are.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FilterChanges {
+    app_name: Option<String>,
+    app_icon: Option<String>,
+    summary: Option<String>,
+    body: Option<String>,
+    actions: Option<Vec<(String, String)>>,
+    urgency: Option<String>,
+    category: Option<String>,
+    expire_timeout: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct FilterOutput {
+    version: u32,
+    #[serde(default)]
+    drop: bool,
+    #[serde(default)]
+    notification: FilterChanges,
+}
+
+impl FilterNotification {
+    fn apply(&mut self, changes: FilterChanges) {
+        let FilterChanges {
+            app_name,
+            app_icon,
+            summary,
+            body,
+            actions,
+            urgency,
+            category,
+            expire_timeout,
+        } = changes;
+        self.app_name = app_name.unwrap_or_else(|| self.app_name.clone());
+        self.app_icon = app_icon.unwrap_or_else(|| self.app_icon.clone());
+        self.summary = summary.unwrap_or_else(|| self.summary.clone());
+        self.body = body.unwrap_or_else(|| self.body.clone());
+        self.actions = actions.unwrap_or_else(|| self.actions.clone());
+        self.urgency = urgency.or_else(|| self.urgency.clone());
+        self.category = category.or_else(|| self.category.clone());
+        self.expire_timeout = expire_timeout.unwrap_or(self.expire_timeout);
+    }
+}
+
+/// Runs one filter to completion. Returns None when it asked for the notification to be dropped.
+async fn run_filter(
+    filter: &FilterConfig,
+    notif: FilterNotification,
+) -> std::result::Result<Option<FilterNotification>, String> {
+    let input = serde_json::to_string(&FilterInput {
+        version: FILTER_PROTOCOL_VERSION,
+        notification: &notif,
+    })
+    .unwrap();
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&filter.command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let run = async {
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        // Write and read at the same time so a filter answering early does not dead lock
+        let write = async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        };
+        let mut output = String::new();
+        let (_, read) = tokio::join!(write, stdout.read_to_string(&mut output));
+        read.map_err(|e| e.to_string())?;
+        let status = child.wait().await.map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("exited with {}", status));
+        }
+        Ok(output)
+    };
+    let output = tokio::time::timeout(Duration::from_millis(filter.timeout), run)
+        .await
+        .map_err(|_| String::from("timed out"))??;
+
+    let output: FilterOutput =
+        serde_json::from_str(&output).map_err(|e| format!("invalid output: {}", e))?;
+    if output.version != FILTER_PROTOCOL_VERSION {
+        return Err(format!("unsupported protocol version {}", output.version));
+    }
+    if output.drop {
+        return Ok(None);
+    }
+    let mut notif = notif;
+    notif.apply(output.notification);
+    Ok(Some(notif))
+}
+
+/// Passes a notification through every filter in order. Returns None if it is to be dropped,
+/// either because a filter asked for it or because a filter which fails closed failed.
+pub async fn apply_filters(
+    filters: &[FilterConfig],
+    mut notif: FilterNotification,
+) -> Option<FilterNotification> {
+    for filter in filters {
+        match run_filter(filter, notif.clone()).await {
+            Ok(Some(filtered)) => notif = filtered,
+            Ok(None) => {
+                log!(
+                    "Notification from {} dropped by filter {}",
+                    notif.app_name,
+                    filter.command
+                );
+                return None;
+            }
+            Err(e) => {
+                log!("Filter {} failed: {}", filter.command, e);
+                eprintln!("Filter {} failed: {}", filter.command, e);
+                if !filter.fail_open {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(notif)
+}
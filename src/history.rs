@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+
+use crate::log;
+use crate::notifdaemon::HistoryNotification;
+use crate::utils::{get_icon_dir, get_state_dir};
+
+fn history_path() -> String {
+    format!("{}/history.jsonl", get_state_dir())
+}
+
+pub fn load_history(max_notifications: u32) -> Vec<HistoryNotification> {
+    let contents = match fs::read_to_string(history_path()) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+    let mut history: Vec<HistoryNotification> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log!("Skipping corrupt history entry: {}", e);
+                None
+            }
+        })
+        .collect();
+    log!("Loaded {} history entries", history.len());
+
+    // The config may have changed since the file was written, so rewrite it if it had to be
+    // trimmed. This also compacts away any corrupt lines.
+    trim_history(&mut history, max_notifications);
+    save_history(&history);
+    history
+}
+
+pub fn append_history(entry: &HistoryNotification) {
+    let line = serde_json::to_string(entry).unwrap();
+    let res = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(history_path())
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = res {
+        log!("Failed to append to history file: {}", e);
+    }
+}
+
+pub fn save_history(history: &[HistoryNotification]) {
+    let mut contents = String::new();
+    for entry in history {
+        contents.push_str(&serde_json::to_string(entry).unwrap());
+        contents.push('\n');
+    }
+    // Write to a temporary file first so a crash never leaves a half written history behind
+    let path = history_path();
+    let tmp_path = format!("{}.tmp", path);
+    let res = fs::write(&tmp_path, contents).and_then(|_| fs::rename(&tmp_path, &path));
+    if let Err(e) = res {
+        log!("Failed to save history file: {}", e);
+    }
+}
+
+/// Drops the oldest entries so that at most `max_notifications` remain. A limit of 0 keeps
+/// everything. Returns whether anything was removed.
+pub fn trim_history(history: &mut Vec<HistoryNotification>, max_notifications: u32) -> bool {
+    let max = max_notifications as usize;
+    if max == 0 || history.len() <= max {
+        return false;
+    }
+    history.drain(..history.len() - max);
+    true
+}
+
+/// Removes the saved image-data icons which are no longer referenced by anything in `in_use`.
+pub fn prune_icons<'a>(in_use: impl Iterator<Item = &'a str>) {
+    let in_use: HashSet<&str> = in_use.collect();
+    let entries = match fs::read_dir(get_icon_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let path_str = path.to_string_lossy();
+        if !in_use.contains(path_str.as_ref()) {
+            log!("Removing unused icon {}", path_str);
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
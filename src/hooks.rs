@@ -0,0 +1,180 @@
+use serde::Serialize;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::HooksConfig;
+use crate::log;
+use crate::notifdaemon::{CloseReason, HistoryNotification, Notification};
+
+/// The notification fields handed to hooks.
+#[derive(Serialize)]
+pub struct HookNotification<'a> {
+    pub id: u32,
+    pub app_name: &'a str,
+    pub summary: &'a str,
+    pub body: &'a str,
+    pub icon: &'a str,
+    pub app_icon: &'a str,
+    pub urgency: &'a str,
+    pub category: &'a str,
+    pub desktop_entry: Option<&'a str>,
+    pub sender: Option<&'a str>,
+    pub received: u64,
+    pub timeout: i32,
+    pub actions: &'a [(String, String)],
+    pub hints: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+impl<'a> HookNotification<'a> {
+    pub fn new(id: u32, notif: &'a Notification) -> Self {
+        HookNotification {
+            id,
+            app_name: &notif.app_name,
+            summary: &notif.summary,
+            body: &notif.body,
+            icon: &notif.icon,
+            app_icon: &notif.app_icon,
+            urgency: &notif.urgency,
+            category: &notif.category,
+            desktop_entry: notif.desktop_entry.as_deref(),
+            sender: notif.sender.as_deref(),
+            received: notif.received,
+            timeout: notif.expire_timeout,
+            actions: &notif.actions,
+            hints: &notif.hints,
+        }
+    }
+
+    pub fn from_history(hist: &'a HistoryNotification) -> Self {
+        HookNotification {
+            id: hist.id,
+            app_name: &hist.app_name,
+            summary: &hist.summary,
+            body: &hist.body,
+            icon: &hist.icon,
+            app_icon: &hist.app_icon,
+            urgency: &hist.urgency,
+            category: &hist.category,
+            desktop_entry: hist.desktop_entry.as_deref(),
+            sender: hist.sender.as_deref(),
+            received: hist.received,
+            timeout: hist.timeout,
+            actions: &hist.actions,
+            hints: &hist.hints,
+        }
+    }
+}
+
+pub enum HookEvent {
+    Notify,
+    Close(CloseReason),
+    Action(String),
+    Reply(String),
+}
+
+#[derive(Serialize)]
+struct HookInput<'a> {
+    event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<&'a str>,
+    notification: &'a HookNotification<'a>,
+}
+
+fn reason_str(reason: CloseReason) -> &'static str {
+    match reason {
+        CloseReason::Expired => "expired",
+        CloseReason::Dismissed => "dismissed",
+        CloseReason::Closed => "closed",
+        CloseReason::Undefined => "undefined",
+    }
+}
+
+/// Runs the hook configured for `event`, if any, without waiting for it. The hook gets the
+/// notification as `END_*` environment variables and as JSON on stdin, and is killed once
+/// `timeout` has passed.
+pub fn run_hook(config: &HooksConfig, event: HookEvent, notif: HookNotification) {
+    let (command, input) = match &event {
+        HookEvent::Notify => (&config.notify, ("notify", None, None, None)),
+        HookEvent::Close(reason) => (
+            &config.close,
+            ("close", Some(reason_str(*reason)), None, None),
+        ),
+        HookEvent::Action(action) => (
+            &config.action,
+            ("action", None, Some(action.as_str()), None),
+        ),
+        HookEvent::Reply(reply) => (&config.reply, ("reply", None, None, Some(reply.as_str()))),
+    };
+    if command.is_empty() {
+        return;
+    }
+    let (event, reason, action, reply) = input;
+    let input = HookInput {
+        event,
+        reason,
+        action,
+        reply,
+        notification: &notif,
+    };
+    let json = serde_json::to_string(&input).unwrap();
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .env("END_EVENT", event)
+        .env("END_ID", notif.id.to_string())
+        .env("END_APP_NAME", notif.app_name)
+        .env("END_SUMMARY", notif.summary)
+        .env("END_BODY", notif.body)
+        .env("END_ICON", notif.icon)
+        .env("END_APP_ICON", notif.app_icon)
+        .env("END_URGENCY", notif.urgency)
+        .env("END_CATEGORY", notif.category)
+        .env("END_DESKTOP_ENTRY", notif.desktop_entry.unwrap_or(""))
+        .env("END_TIMEOUT", notif.timeout.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(reason) = reason {
+        cmd.env("END_REASON", reason);
+    }
+    if let Some(action) = action {
+        cmd.env("END_ACTION", action);
+    }
+    if let Some(reply) = reply {
+        cmd.env("END_REPLY", reply);
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log!("Failed to run {} hook: {}", event, e);
+            eprintln!("Failed to run {} hook: {}", event, e);
+            return;
+        }
+    };
+    let timeout = Duration::from_secs(config.timeout as u64);
+    tokio::spawn(async move {
+        let run = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                // Hooks are free to ignore their input
+                let _ = stdin.write_all(json.as_bytes()).await;
+            }
+            child.wait().await
+        };
+        match tokio::time::timeout(timeout, run).await {
+            Ok(Ok(status)) if !status.success() => {
+                log!("The {} hook failed with {}", event, status);
+            }
+            Ok(Err(e)) => log!("Failed to wait for the {} hook: {}", event, e),
+            Err(_) => log!("The {} hook timed out and was killed", event),
+            Ok(Ok(_)) => {}
+        }
+    });
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::config::LimitsConfig;
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// A token bucket per client. Every notification takes a token, and tokens come back at `rate`
+/// per second up to `burst`.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: HashMap<String, Bucket>,
+}
+
+/// Buckets are only forgotten once there are this many, so a steady sender keeps its state.
+const MAX_BUCKETS: usize = 256;
+
+impl RateLimiter {
+    /// Takes a token from the bucket of the client, which is the unique bus name of `sender`.
+    /// The app name is whatever the sender claims, so it only counts when there is no sender.
+    /// Returns false when the notification should be dropped.
+    pub fn allow(&mut self, limits: &LimitsConfig, sender: Option<&str>, app_name: &str) -> bool {
+        if limits.burst == 0 || limits.rate <= 0.0 {
+            return true;
+        }
+        let client = sender.unwrap_or(app_name);
+        let burst = limits.burst as f64;
+        let now = Instant::now();
+        if self.buckets.len() >= MAX_BUCKETS {
+            // Full buckets are the same as new ones, so they can go
+            self.buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * limits.rate
+                    < burst
+            });
+        }
+        let bucket = self.buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            refilled: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limits.rate).min(burst);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Cuts `text` down to `max` characters, marking the cut with an ellipsis. 0 means no limit.
+pub fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) if max > 0 => format!("{}…", &text[..end]),
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> LimitsConfig {
+        LimitsConfig {
+            burst: 3,
+            rate: 0.001,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn burst_is_enforced() {
+        let limits = limits();
+        let mut limiter = RateLimiter::default();
+        for _ in 0..3 {
+            assert!(limiter.allow(&limits, Some(":1.42"), "app"));
+        }
+        assert!(!limiter.allow(&limits, Some(":1.42"), "app"));
+        // Other clients have their own budget
+        assert!(limiter.allow(&limits, Some(":1.43"), "app"));
+    }
+
+    #[test]
+    fn rotating_app_name_keeps_the_budget() {
+        let limits = limits();
+        let mut limiter = RateLimiter::default();
+        for i in 0..3 {
+            assert!(limiter.allow(&limits, Some(":1.42"), &format!("app{}", i)));
+        }
+        assert!(!limiter.allow(&limits, Some(":1.42"), "app3"));
+        assert!(!limiter.allow(&limits, Some(":1.42"), ""));
+    }
+}
@@ -2,10 +2,21 @@ use std::env;
 use zbus::fdo::Result;
 
 pub mod config;
+pub mod control;
 pub mod ewwface;
+pub mod filters;
 pub mod generator;
+pub mod history;
+pub mod hooks;
+pub mod limits;
+pub mod markup;
+pub mod monitor;
 pub mod notifdaemon;
+pub mod quiet;
+pub mod rules;
+pub mod snooze;
 pub mod socktools;
+pub mod sound;
 pub mod utils;
 
 fn print_help() {
@@ -19,7 +30,30 @@ fn print_help() {
     println!("  daemon - Start the notification daemon");
     println!("  close <id> - Close a notification with the given ID");
     println!("  history <open|close|toggle> - Open, close or toggle the notification history");
-    println!("  action <id> <action> - Perform an action on a notification with the given ID");
+    println!(
+        "  history <expand|collapse> <id> - Expand or collapse the history group of an entry's application"
+    );
+    println!("  history clear - Remove every entry from the history");
+    println!("  history remove <id> - Remove the history entry with the given ID");
+    println!("  history clear-app <id> - Remove all history entries of an entry's application");
+    println!(
+        "  history action <id> <action|--index n> - Invoke an action of a history entry, while its sender is running"
+    );
+    println!(
+        "  action <id> <action|--index n> - Perform an action on a notification with the given ID"
+    );
+    println!("  default <id> - Invoke the default action of a notification, or dismiss it");
+    println!(
+        "  group <expand|collapse|toggle> <id> - Expand or collapse the popup group of a notification's application"
+    );
+    println!("  dnd <on|off|toggle|status> - Control or query Do Not Disturb mode");
+    println!(
+        "  snooze <id> <duration> - Hide a notification and show it again after e.g. 10m or 1h30m"
+    );
+    println!("  status - Show the DND and quiet hours state, the unread count and the snoozed notifications");
+    println!("  list - List the active and snoozed notifications");
+    println!("  pause <id|all> - Pause the timeout of a notification, or of all notifications");
+    println!("  resume <id|all> - Resume the timeout of a notification, or of all notifications");
     println!();
     println!("  generate [css|yuck|all] - Generate the eww config files");
 }
@@ -67,7 +101,6 @@ async fn main() -> Result<()> {
         socktools::run_daemon(cfg).await?;
     } else {
         socktools::send_message(args[1..].to_vec()).await?;
-        println!("Message sent");
     }
 
     Ok(())
@@ -0,0 +1,267 @@
+/// A notification body reduced to markup which is safe to hand to eww's `:markup`.
+#[derive(Default, Debug)]
+pub struct Markup {
+    pub text: String,
+    /// (url, label) of every hyperlink in the body, in order of appearance
+    pub links: Vec<(String, String)>,
+}
+
+/// Link schemes which are turned into actions. Other links are rendered as plain text.
+const LINK_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+/// Escapes text so that Pango shows it verbatim.
+pub fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Resolves the entities allowed by the spec, leaving anything unknown as it is.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..end + 1])
+            .filter(|entity| entity.len() <= 10);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                // Control characters (NUL above all) stay escaped, they break hooks and widgets
+                char::from_u32(code).filter(|c| !c.is_control() || matches!(c, '\t' | '\n'))
+            }
+        });
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns the value of an attribute in the inside of a tag, e.g. `a href="..."`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(pos) = rest.find(name) {
+        let before = rest[..pos].chars().last();
+        let after = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let value = match after.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(char::is_whitespace).next().unwrap_or(""),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Parses the markup subset of the notification spec (b, i, u, a, img and entities) and drops
+/// everything else, keeping the text. Unbalanced tags are closed or ignored, so the result is
+/// always valid Pango markup.
+pub fn sanitize_markup(body: &str) -> Markup {
+    let mut markup = Markup::default();
+    let mut open_tags: Vec<&'static str> = Vec::new();
+    // The url and the label text of the link being parsed
+    let mut link: Option<(String, String)> = None;
+    let mut rest = body;
+
+    while !rest.is_empty() {
+        let (text, tag, next) = match rest.find('<') {
+            Some(start) => match rest[start..].find('>') {
+                Some(end) => (
+                    &rest[..start],
+                    Some(&rest[start + 1..start + end]),
+                    start + end + 1,
+                ),
+                // A lone '<' is just text
+                None => (rest, None, rest.len()),
+            },
+            None => (rest, None, rest.len()),
+        };
+        let text = decode_entities(text);
+        markup.text.push_str(&escape_markup(&text));
+        if let Some((_, label)) = &mut link {
+            label.push_str(&text);
+        }
+        rest = &rest[next..];
+        let tag = match tag {
+            Some(tag) => tag.trim(),
+            None => break,
+        };
+
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag.trim()),
+            None => (false, tag.trim_end_matches('/').trim()),
+        };
+        let name = tag
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        let style = ["b", "i", "u"].into_iter().find(|style| *style == name);
+
+        match (style, name.as_str(), closing) {
+            (Some(style), _, false) => {
+                markup.text.push_str(&format!("<{}>", style));
+                open_tags.push(style);
+            }
+            (Some(style), _, true) => {
+                close_tag(&mut markup.text, &mut open_tags, style);
+                // Closing a style can close a link opened inside of it
+                if link.is_some() && !open_tags.contains(&"a") {
+                    markup.links.push(finish_link(link.take().unwrap()));
+                }
+            }
+            (None, "a", false) => {
+                let href = attribute(tag, "href")
+                    .filter(|href| LINK_SCHEMES.iter().any(|scheme| href.starts_with(scheme)));
+                if let (Some(href), None) = (href, &link) {
+                    markup.text.push_str("<u>");
+                    open_tags.push("a");
+                    link = Some((href, String::new()));
+                }
+            }
+            (None, "a", true) => {
+                if let Some(link) = link.take() {
+                    close_tag(&mut markup.text, &mut open_tags, "a");
+                    markup.links.push(finish_link(link));
+                }
+            }
+            (None, "img", false) => {
+                // Images are not rendered inline, so fall back to their description
+                if let Some(alt) = attribute(tag, "alt") {
+                    markup.text.push_str(&escape_markup(&alt));
+                    if let Some((_, label)) = &mut link {
+                        label.push_str(&alt);
+                    }
+                }
+            }
+            (None, "br", false) => markup.text.push('\n'),
+            _ => {}
+        }
+    }
+
+    if let Some(link) = link {
+        markup.links.push(finish_link(link));
+    }
+    while let Some(tag) = open_tags.pop() {
+        markup.text.push_str(&closing_tag(tag));
+    }
+    markup
+}
+
+/// Links without any text are labelled with their url.
+fn finish_link((href, label): (String, String)) -> (String, String) {
+    let label = label.trim();
+    if label.is_empty() {
+        (href.clone(), href)
+    } else {
+        (href, label.to_string())
+    }
+}
+
+fn closing_tag(tag: &str) -> String {
+    // Links are rendered underlined
+    let tag = if tag == "a" { "u" } else { tag };
+    format!("</{}>", tag)
+}
+
+/// Closes `tag` along with everything opened after it. Tags which are not open are ignored.
+fn close_tag(text: &mut String, open_tags: &mut Vec<&'static str>, tag: &str) {
+    let pos = match open_tags.iter().rposition(|open| *open == tag) {
+        Some(pos) => pos,
+        None => return,
+    };
+    for open in open_tags.drain(pos..).rev() {
+        text.push_str(&closing_tag(open));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_tags_are_kept() {
+        let markup = sanitize_markup("<b>bold</b> <I>italic</I> <u>under</u><br/>line");
+        assert_eq!(markup.text, "<b>bold</b> <i>italic</i> <u>under</u>\nline");
+    }
+
+    #[test]
+    fn other_tags_are_stripped() {
+        let markup = sanitize_markup("<span foreground=\"red\">text</span> <big>x</big>");
+        assert_eq!(markup.text, "text x");
+        let markup = sanitize_markup("<img src=\"a.png\" alt=\"&lt;pic&gt;\"/>");
+        assert_eq!(markup.text, "&lt;pic&gt;");
+    }
+
+    #[test]
+    fn links_are_filtered_by_scheme() {
+        let markup = sanitize_markup(
+            "<a href=\"https://example.com\">site</a> <a href=\"file:///etc/passwd\">file</a>",
+        );
+        assert_eq!(markup.text, "<u>site</u> file");
+        assert_eq!(
+            markup.links,
+            vec![("https://example.com".to_string(), "site".to_string())]
+        );
+        let markup = sanitize_markup("<a href='mailto:me@example.com'></a>");
+        assert_eq!(markup.links[0].1, "mailto:me@example.com");
+    }
+
+    #[test]
+    fn unclosed_tags_are_closed() {
+        assert_eq!(sanitize_markup("<b><i>text").text, "<b><i>text</i></b>");
+        assert_eq!(sanitize_markup("<b>a<i>b</b>c").text, "<b>a<i>b</i></b>c");
+        assert_eq!(sanitize_markup("</b>text</i>").text, "text");
+        assert_eq!(sanitize_markup("1 < 2").text, "1 &lt; 2");
+        let markup = sanitize_markup("<a href=\"http://x.org\">link");
+        assert_eq!(markup.text, "<u>link</u>");
+        assert_eq!(markup.links.len(), 1);
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(decode_entities("&lt;&gt;&amp;&quot;&apos;"), "<>&\"'");
+        assert_eq!(decode_entities("&#65;&#x42;&#X43;"), "ABC");
+        assert_eq!(decode_entities("a&#9;b&#10;c"), "a\tb\nc");
+        assert_eq!(decode_entities("&unknown; & &amp"), "&unknown; & &amp");
+        // Control characters and invalid code points stay escaped
+        assert_eq!(decode_entities("&#0;&#x1b;&#127;"), "&#0;&#x1b;&#127;");
+        assert_eq!(decode_entities("&#xD800;&#x110000;"), "&#xD800;&#x110000;");
+        assert_eq!(sanitize_markup("&#0;").text, "&amp;#0;");
+    }
+}
@@ -0,0 +1,217 @@
+use std::env;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::log;
+
+/// How long the compositor or the command gets to answer, as the notification waits for it.
+const LOOKUP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The i3 IPC message Sway answers with the list of outputs.
+const SWAY_GET_OUTPUTS: u32 = 3;
+
+async fn connect(path: &str) -> Option<UnixStream> {
+    match UnixStream::connect(path).await {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            log!("Failed to connect to {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn hyprland_socket() -> Option<String> {
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    // Hyprland moved its sockets from /tmp to the runtime dir
+    let runtime = env::var("XDG_RUNTIME_DIR")
+        .map(|dir| format!("{}/hypr/{}/.socket.sock", dir, signature))
+        .ok()
+        .filter(|path| std::path::Path::new(path).exists());
+    Some(runtime.unwrap_or_else(|| format!("/tmp/hypr/{}/.socket.sock", signature)))
+}
+
+/// Picks the name of the focused output from a JSON list of outputs, which is what both Hyprland
+/// and Sway answer with.
+fn focused_in(outputs: &[u8]) -> Option<String> {
+    let outputs: Vec<serde_json::Value> = match serde_json::from_slice(outputs) {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            log!("Failed to parse the outputs of the compositor: {}", e);
+            return None;
+        }
+    };
+    outputs
+        .iter()
+        .find(|output| output["focused"].as_bool() == Some(true))
+        .and_then(|output| output["name"].as_str())
+        .map(str::to_string)
+}
+
+async fn hyprland_focused(socket: &str) -> Option<String> {
+    let mut stream = connect(socket).await?;
+    stream.write_all(b"j/monitors").await.ok()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.ok()?;
+    focused_in(&response)
+}
+
+async fn sway_focused(socket: &str) -> Option<String> {
+    let mut stream = connect(socket).await?;
+    let mut request = b"i3-ipc".to_vec();
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&SWAY_GET_OUTPUTS.to_ne_bytes());
+    stream.write_all(&request).await.ok()?;
+    // The reply has the same header, followed by the payload
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).await.ok()?;
+    if &header[..6] != b"i3-ipc" {
+        log!("Unexpected reply from {}", socket);
+        return None;
+    }
+    let length = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).await.ok()?;
+    focused_in(&payload)
+}
+
+async fn command_focused(command: &str) -> Option<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !name.is_empty()).then_some(name)
+}
+
+/// The name of the output which has the focus, as configured by `focused_monitor`. None means
+/// popups go to every window, either because that is configured or because the compositor
+/// could not be asked in time.
+pub async fn focused_output(cfg: &Config) -> Option<String> {
+    if cfg.focused_monitor == "all" {
+        return None;
+    }
+    let socket = Some(cfg.focused_monitor_socket.clone()).filter(|socket| !socket.is_empty());
+    let lookup = async {
+        match cfg.focused_monitor.as_str() {
+            "hyprland" => hyprland_focused(&socket.or_else(hyprland_socket)?).await,
+            "sway" => sway_focused(&socket.or_else(|| env::var("SWAYSOCK").ok())?).await,
+            "command" => command_focused(&cfg.focused_monitor_command).await,
+            mode => {
+                log!("Unknown focused_monitor {}", mode);
+                None
+            }
+        }
+    };
+    let output = match tokio::time::timeout(LOOKUP_TIMEOUT, lookup).await {
+        Ok(output) => output,
+        Err(_) => {
+            log!("Looking up the focused output timed out");
+            None
+        }
+    };
+    log!("Focused output: {:?}", output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    fn socket_path(name: &str) -> String {
+        let path = format!(
+            "{}/end-rs-test-{}-{}.sock",
+            env::temp_dir().display(),
+            std::process::id(),
+            name
+        );
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn config(mode: &str, socket: &str) -> Config {
+        Config {
+            focused_monitor: mode.to_string(),
+            focused_monitor_socket: socket.to_string(),
+            ..Default::default()
+        }
+    }
+
+    const OUTPUTS: &str = r#"[{"name":"DP-1","focused":false},{"name":"HDMI-A-1","focused":true}]"#;
+
+    #[tokio::test]
+    async fn hyprland_focused_monitor() {
+        let path = socket_path("hyprland");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 10];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"j/monitors");
+            stream.write_all(OUTPUTS.as_bytes()).unwrap();
+        });
+
+        let output = focused_output(&config("hyprland", &path)).await;
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(output.as_deref(), Some("HDMI-A-1"));
+    }
+
+    #[tokio::test]
+    async fn sway_focused_output() {
+        let path = socket_path("sway");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 14];
+            stream.read_exact(&mut header).unwrap();
+            assert_eq!(&header[..6], b"i3-ipc");
+            assert_eq!(u32::from_ne_bytes(header[6..10].try_into().unwrap()), 0);
+            assert_eq!(
+                u32::from_ne_bytes(header[10..14].try_into().unwrap()),
+                SWAY_GET_OUTPUTS
+            );
+            let mut reply = b"i3-ipc".to_vec();
+            reply.extend_from_slice(&(OUTPUTS.len() as u32).to_ne_bytes());
+            reply.extend_from_slice(&SWAY_GET_OUTPUTS.to_ne_bytes());
+            reply.extend_from_slice(OUTPUTS.as_bytes());
+            stream.write_all(&reply).unwrap();
+        });
+
+        let output = focused_output(&config("sway", &path)).await;
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(output.as_deref(), Some("HDMI-A-1"));
+    }
+
+    #[tokio::test]
+    async fn unresponsive_compositor_times_out() {
+        let path = socket_path("silent");
+        // Accepts the connection but never answers
+        let _listener = UnixListener::bind(&path).unwrap();
+
+        let output = focused_output(&config("hyprland", &path)).await;
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(output, None);
+    }
+
+    #[tokio::test]
+    async fn focused_output_from_command() {
+        let mut cfg = config("command", "");
+        cfg.focused_monitor_command = "echo DP-2".to_string();
+        assert_eq!(focused_output(&cfg).await.as_deref(), Some("DP-2"));
+
+        cfg.focused_monitor_command = "sleep 10".to_string();
+        let start = std::time::Instant::now();
+        assert_eq!(focused_output(&cfg).await, None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,22 +1,41 @@
 #![allow(clippy::too_many_arguments)]
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
 use zbus::fdo::Result;
 use zbus::interface;
+use zbus::message::Header;
 use zbus::object_server::SignalEmitter;
 use zvariant::Value;
 
-use crate::config::Config;
+use crate::config::{Config, NotificationWindow};
+use crate::control::{publish_status, ControlStatus};
 use crate::ewwface::{
-    eww_close_history, eww_close_notifications, eww_close_window, eww_toggle_history,
-    eww_update_and_open_history, eww_update_history, eww_update_notifications,
+    eww_close_history, eww_close_notifications, eww_close_popups, eww_close_window,
+    eww_create_reply_widget, eww_open_window, eww_update_and_open_history, eww_update_history,
+    eww_update_notifications, eww_update_osd, eww_update_value, history_actions, popup_actions,
 };
+use crate::filters::{apply_filters, FilterNotification};
+use crate::history::{append_history, prune_icons, save_history, trim_history};
+use crate::hooks::{run_hook, HookEvent, HookNotification};
+use crate::limits::{truncate, RateLimiter};
 use crate::log;
-use crate::utils::{find_icon, save_icon};
+use crate::markup::{escape_markup, sanitize_markup};
+use crate::monitor::focused_output;
+use crate::quiet::QuietSchedule;
+use crate::rules::{urgency_from_str, RuleInput, RuleSet};
+use crate::snooze::{load_snoozed, save_snoozed, SnoozedNotification};
+use crate::socktools::DaemonActions;
+use crate::sound::{play_sound, Sound};
+use crate::utils::{
+    decode_icon, find_icon, format_duration, get_state_dir, parse_duration, save_icon, unix_time,
+    IconImage,
+};
 
 pub struct Notification {
     pub app_name: String,
@@ -26,70 +45,290 @@ pub struct Notification {
     pub body: String,
     pub urgency: String,
     pub actions: Vec<(String, String)>,
+    /// (url, label) of the hyperlinks in the body, shown as extra actions
+    pub links: Vec<(String, String)>,
+    pub window: Option<NotificationWindow>,
+    pub var: Option<String>,
+    pub widget: Option<String>,
+    /// Progress in percent from the `value` hint
+    pub value: Option<i32>,
+    /// Notifications with the same application and stack tag replace each other
+    pub stack_tag: Option<String>,
+    /// Shown in the OSD window rather than as a popup, see `NotifyRequest::is_osd`
+    pub osd: bool,
+    /// Stays after an action is invoked, until it is closed explicitly
+    pub resident: bool,
+    /// Action keys are icon names, to be shown instead of the action text
+    pub action_icons: bool,
+    /// Where the sender would like the notification to appear on screen
+    pub position: Option<(i32, i32)>,
+    pub category: String,
+    pub desktop_entry: Option<String>,
+    /// Unix timestamp in seconds
+    pub received: u64,
+    /// Unique bus name of the sender
+    pub sender: Option<String>,
+    /// Hints the daemon does not use itself, passed through to the widgets
+    pub hints: serde_json::Map<String, serde_json::Value>,
+    /// Timeout in milliseconds, 0 meaning never
+    pub expire_timeout: i32,
+    /// Whether the notification is on screen, as opposed to waiting in the overflow queue
+    pub visible: bool,
+    /// Time left until the notification expires, counted from `timeout_started` while the
+    /// timer is running
+    pub remaining: Duration,
+    pub timeout_started: Option<Instant>,
+    /// Paused by the user, e.g. while hovering the popup
+    pub paused: bool,
     pub timeout_cancelled: bool,
     pub timeout_future: Option<JoinHandle<()>>,
+    /// Identifies the currently running timer, so a stale timer (e.g. of a replaced notification)
+    /// can never expire the notification
+    pub generation: u64,
+}
+
+/// The action invoked by clicking the notification itself. It is not shown as a button.
+pub const DEFAULT_ACTION: &str = "default";
+
+/// Actions named `snooze:<duration>` snooze the notification, see `Config::snooze_presets`.
+pub const SNOOZE_ACTION_PREFIX: &str = "snooze:";
+
+/// Actions named `link:<n>` open the n-th hyperlink of the body.
+pub const LINK_ACTION_PREFIX: &str = "link:";
+
+/// Why a notification was closed, as defined by the NotificationClosed signal of the spec.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The notification timed out
+    Expired = 1,
+    /// The user dismissed the notification, either directly or by invoking an action or replying
+    Dismissed = 2,
+    /// The sender closed the notification through CloseNotification
+    Closed = 3,
+    /// The notification was never shown, e.g. because it was dropped by a rule or suppressed
+    Undefined = 4,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct HistoryNotification {
+    #[serde(default)]
+    pub id: u32,
     pub app_name: String,
     pub icon: String,
     pub app_icon: String,
     pub summary: String,
     pub body: String,
     pub urgency: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub desktop_entry: Option<String>,
+    #[serde(default)]
+    pub received: u64,
+    #[serde(default)]
+    pub sender: Option<String>,
+    #[serde(default)]
+    pub timeout: i32,
+    #[serde(default)]
+    pub hints: serde_json::Map<String, serde_json::Value>,
+    /// Kept so the action can still be invoked from the history while the sender is running
+    #[serde(default)]
+    pub actions: Vec<(String, String)>,
+    /// Whether the history has been opened since the notification arrived. Entries from before
+    /// this was tracked count as read.
+    #[serde(default = "default_read")]
+    pub read: bool,
 }
 
-pub struct NotificationDaemon {
-    pub config: Arc<Config>,
-    pub notifications: Arc<Mutex<HashMap<u32, Notification>>>,
-    pub notifications_history: Arc<RwLock<Vec<HistoryNotification>>>,
-    pub connection: zbus::Connection,
-    pub next_id: u32,
+fn default_read() -> bool {
+    true
 }
 
-#[interface(name = "org.freedesktop.Notifications")]
-impl NotificationDaemon {
-    pub async fn notify(
-        &mut self,
+#[derive(Default, Serialize, Deserialize)]
+pub struct DndState {
+    pub enabled: bool,
+    pub missed: u32,
+}
+
+impl DndState {
+    fn path() -> String {
+        format!("{}/dnd.json", get_state_dir())
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let state = serde_json::to_string(self).unwrap();
+        if let Err(e) = std::fs::write(Self::path(), state) {
+            log!("Failed to save DND state: {}", e);
+        }
+    }
+}
+
+/// A Notify call with its hints already parsed and its icons resolved, so the state task does not
+/// have to block on icon lookups.
+#[derive(Default)]
+pub struct NotifyRequest {
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub icon: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// The summary and body as sent, before escaping and sanitizing, for rules to match on
+    pub raw_summary: String,
+    pub raw_body: String,
+    pub actions: Vec<(String, String)>,
+    pub links: Vec<(String, String)>,
+    pub urgency: Option<u8>,
+    pub category: String,
+    pub transient: bool,
+    pub value: Option<i32>,
+    pub stack_tag: Option<String>,
+    pub resident: bool,
+    pub action_icons: bool,
+    pub position: Option<(i32, i32)>,
+    pub suppress_sound: bool,
+    pub sound_file: Option<String>,
+    pub sound_name: Option<String>,
+    pub desktop_entry: Option<String>,
+    pub received: u64,
+    pub sender: Option<String>,
+    pub hints: serde_json::Map<String, serde_json::Value>,
+    pub expire_timeout: i32,
+    /// Dropped by a filter. The request only serves to give the notification an id
+    pub dropped: bool,
+    /// The focused output, looked up before the request reaches the state task so a slow
+    /// compositor never holds up the daemon
+    pub screen: Option<String>,
+    /// Image data, which takes precedence over `icon` once saved
+    pub image: Option<IconImage>,
+}
+
+/// Hints which are handled by the daemon. Everything else is passed through to the widgets.
+const KNOWN_HINTS: [&str; 19] = [
+    "urgency",
+    "category",
+    "desktop-entry",
+    "image-data",
+    "image_data",
+    "icon_data",
+    "image-path",
+    "image_path",
+    "resident",
+    "transient",
+    "suppress-sound",
+    "sound-file",
+    "sound-name",
+    "action-icons",
+    "x",
+    "y",
+    "value",
+    "x-canonical-private-synchronous",
+    "x-dunst-stack-tag",
+];
+
+/// Converts a hint to JSON. Values without a JSON equivalent, like file descriptors, become null.
+pub fn hint_to_json(value: &Value<'_>) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Value::U8(value) => Json::from(*value),
+        Value::Bool(value) => Json::from(*value),
+        Value::I16(value) => Json::from(*value),
+        Value::U16(value) => Json::from(*value),
+        Value::I32(value) => Json::from(*value),
+        Value::U32(value) => Json::from(*value),
+        Value::I64(value) => Json::from(*value),
+        Value::U64(value) => Json::from(*value),
+        Value::F64(value) => Json::from(*value),
+        Value::Str(value) => Json::from(value.as_str()),
+        Value::Signature(value) => Json::from(value.to_string()),
+        Value::ObjectPath(value) => Json::from(value.as_str()),
+        Value::Value(value) => hint_to_json(value),
+        Value::Array(array) => Json::Array(array.inner().iter().map(hint_to_json).collect()),
+        Value::Structure(structure) => {
+            Json::Array(structure.fields().iter().map(hint_to_json).collect())
+        }
+        Value::Dict(dict) => Json::Object(
+            dict.iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Value::Str(key) => key.to_string(),
+                        key => key.to_string(),
+                    };
+                    (key, hint_to_json(value))
+                })
+                .collect(),
+        ),
+        _ => Json::Null,
+    }
+}
+
+fn hint_str(hints: &HashMap<&str, Value<'_>>, name: &str) -> Option<String> {
+    match hints.get(name)? {
+        Value::Str(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn hint_bool(hints: &HashMap<&str, Value<'_>>, name: &str) -> bool {
+    match hints.get(name) {
+        Some(Value::Bool(value)) => *value,
+        // Some clients send booleans as bytes
+        Some(Value::U8(value)) => *value != 0,
+        _ => false,
+    }
+}
+
+fn hint_i32(hints: &HashMap<&str, Value<'_>>, name: &str) -> Option<i32> {
+    match hints.get(name)? {
+        Value::I32(value) => Some(*value),
+        Value::U32(value) => Some(*value as i32),
+        Value::I64(value) => Some(*value as i32),
+        Value::I16(value) => Some(*value as i32),
+        Value::U8(value) => Some(*value as i32),
+        _ => None,
+    }
+}
+
+impl NotifyRequest {
+    pub fn parse(
+        config: &Config,
+        sender: Option<String>,
         app_name: &str,
         replaces_id: u32,
         app_icon: &str,
         summary: &str,
         body: &str,
         actions: Vec<&str>,
-        hints: HashMap<&str, zvariant::Value<'_>>,
+        hints: &HashMap<&str, Value<'_>>,
         expire_timeout: i32,
-    ) -> Result<u32> {
-        log!("Notifying {} - {}", app_name, body);
-        let id = if replaces_id != 0 {
-            replaces_id
-        } else {
-            self.next_id += 1;
-            self.next_id
-        };
-        log!("ID: {}", id);
+    ) -> Self {
         let app_icon = app_icon.strip_prefix("file://").unwrap_or(app_icon);
-        let icon = hints
-            .get("image_data")
-            .and_then(|value| match value {
-                Value::Structure(icon_data) => save_icon(icon_data, id),
+        // The spec gives image data precedence over image-path, which goes before app_icon. The
+        // image data is saved once the notification reaches the state task.
+        let image = ["image-data", "image_data", "icon_data"]
+            .iter()
+            .find_map(|hint| match hints.get(hint) {
+                Some(Value::Structure(icon_data)) => {
+                    decode_icon(icon_data, config.limits.max_image_size)
+                }
                 _ => None,
-            })
-            .or_else(|| {
-                hints.get("image-data").and_then(|value| match value {
-                    Value::Structure(icon_data) => save_icon(icon_data, id),
-                    _ => None,
-                })
-            })
-            .or_else(|| {
-                hints.get("icon_data").and_then(|value| match value {
-                    Value::Structure(icon_data) => save_icon(icon_data, id),
-                    _ => None,
-                })
+            });
+        let icon = hint_str(hints, "image-path")
+            .or_else(|| hint_str(hints, "image_path"))
+            .map(|path| {
+                let path = path.strip_prefix("file://").unwrap_or(&path);
+                find_icon(path, config).unwrap_or_else(|| path.to_string())
             })
             .or_else(|| {
                 if !app_name.is_empty() {
-                    find_icon(app_icon, &self.config).or_else(|| Some(app_icon.to_string()))
+                    find_icon(app_icon, config).or_else(|| Some(app_icon.to_string()))
                 } else {
                     None
                 }
@@ -97,35 +336,24 @@ impl NotificationDaemon {
             .unwrap_or_else(|| app_icon.to_string());
 
         log!("Icon: {}", icon);
-        let app_icon = find_icon(app_name, &self.config).unwrap_or("".into());
-
+        // The desktop entry names the application's icon more reliably than its display name
+        let desktop_entry = hint_str(hints, "desktop-entry");
+        let app_icon = desktop_entry
+            .as_deref()
+            .and_then(|entry| find_icon(entry, config))
+            .or_else(|| find_icon(app_name, config))
+            .unwrap_or("".into());
         log!("AppIcon: {}", app_icon);
+
         let urgency = hints.get("urgency").and_then(|value| match value {
             Value::U8(urgency) => Some(*urgency),
             _ => None,
         });
-
-        let mut expire_timeout = expire_timeout;
-        if expire_timeout < 0 {
-            match urgency {
-                Some(0) => expire_timeout = self.config.timeout.low as i32 * 1000,
-                Some(1) => expire_timeout = self.config.timeout.normal as i32 * 1000,
-                Some(2) => expire_timeout = self.config.timeout.critical as i32 * 1000,
-                _ => expire_timeout = self.config.timeout.normal as i32 * 1000,
-            }
-        }
-
-        let urgency_str = match urgency {
-            Some(0) => "low",
-            Some(1) => "normal",
-            Some(2) => "critical",
-            _ => "normal",
-        };
-        log!("Expire timeout: {}", expire_timeout);
+        let category = hint_str(hints, "category").unwrap_or_default();
 
         // create an actions vector of type Vec<(String, String)> where even elements are keys and
         // odd elements are values
-        let actions: Vec<(String, String)> = actions
+        let mut actions: Vec<(String, String)> = actions
             .chunks(2)
             .map(|chunk| {
                 let key = chunk.first().unwrap_or(&"").to_string();
@@ -133,110 +361,1681 @@ impl NotificationDaemon {
                 (key, value)
             })
             .collect();
+        let max_actions = config.limits.max_actions;
+        if max_actions > 0 && actions.len() > max_actions {
+            log!(
+                "Dropping {} actions of {} over the limit",
+                actions.len() - max_actions,
+                app_name
+            );
+            actions.truncate(max_actions);
+        }
 
-        let is_transient = hints
-            .get("transient")
-            .and_then(|value| match value {
-                Value::Bool(transient) => Some(*transient),
-                _ => None,
-            })
-            .unwrap_or(false);
+        let transient = hint_bool(hints, "transient");
+        let value = hint_i32(hints, "value");
+        let stack_tag = ["x-canonical-private-synchronous", "x-dunst-stack-tag"]
+            .iter()
+            .find_map(|hint| hints.get(hint))
+            .map(|value| match value {
+                Value::Str(tag) => tag.to_string(),
+                value => value.to_string(),
+            });
 
-        if !is_transient {
-            log!("Notification is not transient");
-            let history_notification = HistoryNotification {
-                app_name: app_name.to_string(),
-                icon: icon.clone(),
-                app_icon: app_icon.clone(),
-                summary: summary.to_string(),
-                body: body.to_string(),
-                urgency: urgency_str.to_string(),
-            };
-            let mut notifications_history = self.notifications_history.write().await;
-            notifications_history.push(history_notification);
-            log!("Updated history");
-            // Release the lock before updating the notifications
-            if notifications_history.len() > self.config.max_notifications as usize {
-                notifications_history.remove(0);
+        // The summary is plain text, the body may contain the markup subset of the spec
+        let raw_summary = truncate(summary, config.limits.max_summary_length);
+        let raw_body = truncate(body, config.limits.max_body_length);
+        let body = sanitize_markup(&raw_body);
+
+        NotifyRequest {
+            app_name: app_name.to_string(),
+            replaces_id,
+            icon,
+            app_icon,
+            summary: escape_markup(&raw_summary),
+            body: body.text,
+            raw_summary,
+            raw_body,
+            actions,
+            links: body.links,
+            urgency,
+            category,
+            transient,
+            value: value.map(|value| value.clamp(0, 100)),
+            stack_tag,
+            resident: hint_bool(hints, "resident"),
+            action_icons: hint_bool(hints, "action-icons"),
+            position: hint_i32(hints, "x").zip(hint_i32(hints, "y")),
+            suppress_sound: hint_bool(hints, "suppress-sound"),
+            sound_file: hint_str(hints, "sound-file"),
+            sound_name: hint_str(hints, "sound-name"),
+            desktop_entry,
+            received: unix_time(),
+            sender,
+            hints: hints
+                .iter()
+                .filter(|(name, _)| !KNOWN_HINTS.contains(name))
+                .map(|(name, value)| (name.to_string(), hint_to_json(value)))
+                .collect(),
+            expire_timeout,
+            dropped: false,
+            screen: None,
+            image,
+        }
+    }
+
+    /// Volume, brightness and similar keys send a progress value along with a stack tag. These are
+    /// shown as an on-screen display.
+    pub fn is_osd(&self) -> bool {
+        self.value.is_some() && self.stack_tag.is_some()
+    }
+}
+
+/// An active or snoozed notification, as listed by `end-rs list` and the control interface.
+#[derive(Serialize, zvariant::Type)]
+pub struct ListedNotification {
+    pub id: u32,
+    pub app_name: String,
+    pub summary: String,
+    pub body: String,
+    pub urgency: String,
+    /// "shown", "queued" or "snoozed"
+    pub state: String,
+}
+
+/// Everything that can change the daemon state. D-Bus calls, socket messages and timers all go
+/// through the same channel, so they are applied one after the other and never lost.
+pub enum DaemonCommand {
+    Notify(Box<NotifyRequest>, oneshot::Sender<u32>),
+    Close(u32, CloseReason),
+    Expire(u32, u64),
+    /// Shows a snoozed notification again, if it is still snoozed until the given time
+    Wake(u32, u64),
+    UpdateHistory,
+    /// Quiet hours may have started or ended
+    QuietHours,
+    Action(DaemonActions, oneshot::Sender<String>),
+    List(oneshot::Sender<Vec<ListedNotification>>),
+}
+
+fn urgency_to_str(urgency: Option<u8>) -> &'static str {
+    match urgency {
+        Some(0) => "low",
+        Some(1) => "normal",
+        Some(2) => "critical",
+        _ => "normal",
+    }
+}
+
+async fn emit_notification_closed(connection: &zbus::Connection, id: u32, reason: CloseReason) {
+    let dest: Option<&str> = None;
+    let res = connection
+        .emit_signal(
+            dest,
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "NotificationClosed",
+            &(id, reason as u32),
+        )
+        .await;
+    if let Err(e) = res {
+        log!("Failed to emit NotificationClosed for {}: {}", id, e);
+    }
+}
+
+/// The daemon state. It is owned by a single task (see [`run_state`]) which receives
+/// [`DaemonCommand`]s, so none of it needs locking.
+pub struct DaemonState {
+    pub config: Arc<Config>,
+    pub notifications: HashMap<u32, Notification>,
+    pub notifications_history: Vec<HistoryNotification>,
+    pub connection: zbus::Connection,
+    pub next_id: u32,
+    pub next_generation: u64,
+    pub dnd: DndState,
+    pub rules: RuleSet,
+    pub expanded_groups: HashSet<String>,
+    pub expanded_history_groups: HashSet<String>,
+    /// All timeouts are held while the history window is open
+    pub history_open: bool,
+    pub snoozed: HashMap<u32, SnoozedNotification>,
+    pub quiet_hours: QuietSchedule,
+    /// The output popups are shown on, when they follow the focus
+    pub screen: Option<String>,
+    /// Backs the properties of the control interface
+    pub control: watch::Sender<ControlStatus>,
+    /// Whether quiet hours were active when last checked, to notice them ending
+    pub quiet_active: bool,
+    /// Used by timers to report back to the state task
+    pub tx: mpsc::Sender<DaemonCommand>,
+}
+
+pub async fn run_state(mut state: DaemonState, mut rx: mpsc::Receiver<DaemonCommand>) {
+    while let Some(command) = rx.recv().await {
+        match command {
+            DaemonCommand::Notify(request, reply) => {
+                let id = state.notify(*request).await;
+                let _ = reply.send(id);
+            }
+            DaemonCommand::Close(id, reason) => {
+                state.close(id, reason).await;
+            }
+            DaemonCommand::Expire(id, generation) => {
+                state.expire(id, generation).await;
+            }
+            DaemonCommand::Wake(id, until) => {
+                state.wake(id, until);
+            }
+            DaemonCommand::UpdateHistory => {
+                state.update_history();
+            }
+            DaemonCommand::QuietHours => {
+                state.check_quiet_hours().await;
+            }
+            DaemonCommand::Action(action, reply) => {
+                let response = state.handle_action(action).await;
+                let _ = reply.send(response);
+            }
+            DaemonCommand::List(reply) => {
+                let _ = reply.send(state.list());
+            }
+        }
+    }
+}
+
+impl DaemonState {
+    pub async fn notify(&mut self, request: NotifyRequest) -> u32 {
+        let osd = request.is_osd();
+        let NotifyRequest {
+            app_name,
+            replaces_id,
+            icon,
+            app_icon,
+            summary,
+            body,
+            raw_summary,
+            raw_body,
+            actions,
+            links,
+            urgency,
+            category,
+            transient,
+            value,
+            stack_tag,
+            resident,
+            action_icons,
+            position,
+            suppress_sound,
+            sound_file,
+            sound_name,
+            desktop_entry,
+            received,
+            sender,
+            hints,
+            expire_timeout,
+            dropped,
+            screen,
+            image,
+        } = request;
+        log!("Notifying {} - {}", app_name, body);
+
+        if replaces_id == 0 && stack_tag.is_none() && self.config.limits.collapse_identical {
+            let identical = self.notifications.iter().find(|(_, notif)| {
+                notif.app_name == app_name
+                    && notif.summary == summary
+                    && notif.body == body
+                    && notif.actions == actions
+            });
+            if let Some((&id, _)) = identical {
+                log!("Notification collapsed into identical ID {}", id);
+                self.restart_timeout(id);
+                return id;
+            }
+        }
+
+        // A snoozed notification is replaced by its tag as well, ending the snooze
+        let replaces_id = match &stack_tag {
+            Some(tag)
+                if !self.notifications.contains_key(&replaces_id)
+                    && !self.snoozed.contains_key(&replaces_id) =>
+            {
+                let active = self
+                    .notifications
+                    .iter()
+                    .map(|(id, notif)| (*id, &notif.app_name, &notif.stack_tag));
+                let snoozed = self
+                    .snoozed
+                    .iter()
+                    .map(|(id, entry)| (*id, &entry.app_name, &entry.stack_tag));
+                active
+                    .chain(snoozed)
+                    .find(|(_, app, stack_tag)| {
+                        **app == app_name && stack_tag.as_ref() == Some(tag)
+                    })
+                    .map(|(id, _, _)| id)
+                    .unwrap_or(replaces_id)
             }
+            _ => replaces_id,
+        };
+        // Only reuse the id of a notification that is still active or snoozed
+        let replaced = replaces_id != 0
+            && (self.notifications.contains_key(&replaces_id)
+                || self.snoozed.contains_key(&replaces_id));
+        let id = if replaced {
+            replaces_id
+        } else {
+            self.next_id += 1;
+            self.next_id
+        };
+        // The replacement is shown right away, ending the snooze
+        if self.snoozed.remove(&id).is_some() {
+            self.snoozed_changed();
+        }
+        log!("ID: {}", id);
+        if dropped {
+            self.discard(id, replaced).await;
+            return id;
+        }
+
+        let outcome = self.rules.evaluate(&RuleInput {
+            app_name: &app_name,
+            summary: &raw_summary,
+            body: &raw_body,
+            category: &category,
+            urgency: urgency_to_str(urgency),
+        });
+        if outcome.drop {
+            log!("Notification with ID {} dropped by rule", id);
+            self.discard(id, replaced).await;
+            return id;
+        }
+        let urgency = outcome.urgency.or(urgency);
+        // Saved here rather than while parsing, so the icon cannot be pruned before it is used
+        let icon = image.as_ref().and_then(save_icon).unwrap_or(icon);
+
+        let mut expire_timeout = expire_timeout;
+        if let Some(timeout) = outcome.timeout {
+            expire_timeout = timeout as i32 * 1000;
+        } else if expire_timeout < 0 && osd {
+            expire_timeout = self.config.timeout.osd as i32 * 1000;
+        } else if expire_timeout < 0 {
+            match urgency {
+                Some(0) => expire_timeout = self.config.timeout.low as i32 * 1000,
+                Some(1) => expire_timeout = self.config.timeout.normal as i32 * 1000,
+                Some(2) => expire_timeout = self.config.timeout.critical as i32 * 1000,
+                _ => expire_timeout = self.config.timeout.normal as i32 * 1000,
+            }
+        }
 
-            drop(notifications_history);
+        let urgency_str = urgency_to_str(urgency);
+        log!("Expire timeout: {}", expire_timeout);
+
+        let is_transient = outcome.transient.unwrap_or(transient);
+
+        // OSD notifications only reflect a current state, which is not worth keeping
+        if !is_transient && !outcome.skip_history && !osd {
+            log!("Notification is not transient");
+            self.record_history(
+                HistoryNotification {
+                    id,
+                    app_name: app_name.clone(),
+                    icon: icon.clone(),
+                    app_icon: app_icon.clone(),
+                    summary: summary.clone(),
+                    body: body.clone(),
+                    urgency: urgency_str.to_string(),
+                    category: category.clone(),
+                    desktop_entry: desktop_entry.clone(),
+                    received,
+                    sender: sender.clone(),
+                    timeout: expire_timeout,
+                    hints: hints.clone(),
+                    actions: actions.clone(),
+                    read: false,
+                },
+                replaced,
+            );
             if self.config.update_history {
-                self.update_history().await?;
+                self.update_history();
                 log!("Updated history for update_history");
             }
             log!("Updated history");
         }
 
-        let mut join_handle = None;
-        if expire_timeout != 0 {
-            // Spawn a task to handle timeout
-            let notifications = Arc::clone(&self.notifications);
-            let config_thread = Arc::clone(&self.config);
-            join_handle = Some(tokio::spawn(async move {
-                sleep(Duration::from_millis(expire_timeout as u64)).await;
-                let notifications = notifications.try_lock();
-                if let Ok(mut notifications) = notifications {
-                    if let Some(notif) = notifications.remove(&id) {
-                        if !notif.timeout_cancelled {
-                            eww_update_notifications(&config_thread, &notifications);
-                            if notifications.is_empty() {
-                                eww_close_notifications(&config_thread);
-                            }
-                        }
-                    }
-                }
-            }));
+        // OSD notifications are feedback to something the user just did, so DND does not hide them
+        if (self.dnd.enabled || self.in_quiet_hours())
+            && !osd
+            && urgency != Some(2)
+            && !self.config.dnd_allowed_apps.contains(&app_name)
+        {
+            self.dnd.missed += 1;
+            self.dnd.save();
+            self.update_status();
+            log!("Notification with ID {} suppressed by DND", id);
+            self.discard(id, replaced).await;
+            return id;
+        }
+
+        // The sound file goes before the name. Updates of a notification and OSD changes only
+        // make a sound when they ask for one.
+        let default_sound = match urgency_str {
+            "low" => &self.config.sound.low,
+            "critical" => &self.config.sound.critical,
+            _ => &self.config.sound.normal,
+        };
+        let sound = match (sound_file, sound_name) {
+            (Some(file), _) => Some(Sound::File(file)),
+            (None, Some(name)) => Some(Sound::Name(name)),
+            _ if replaced || osd || default_sound.is_empty() => None,
+            _ if default_sound.contains('/') => Some(Sound::File(default_sound.clone())),
+            _ => Some(Sound::Name(default_sound.clone())),
+        };
+        if let Some(sound) = sound {
+            // Notifications which get through DND still do so silently
+            if suppress_sound || self.config.sound.mute || self.dnd.enabled || self.in_quiet_hours()
+            {
+                log!("Notification with ID {} plays no sound", id);
+            } else {
+                play_sound(&self.config.sound, sound);
+            }
         }
 
+        // The pointer is likely still on the popup being replaced
+        let paused = self
+            .notifications
+            .get(&id)
+            .is_some_and(|notif| notif.paused);
         let notification = Notification {
-            app_name: app_name.to_string(),
-            icon: icon.clone(),
+            app_name,
+            icon,
             app_icon,
             actions,
-            summary: summary.to_string(),
-            body: body.to_string(),
+            links,
+            summary,
+            body,
             urgency: urgency_str.to_string(),
+            window: outcome.window,
+            var: outcome.var,
+            widget: outcome.widget,
+            value,
+            stack_tag,
+            osd,
+            resident,
+            action_icons,
+            position,
+            category,
+            desktop_entry,
+            received,
+            sender,
+            hints,
+            expire_timeout,
+            visible: false,
+            remaining: Duration::from_millis(expire_timeout as u64),
+            timeout_started: None,
+            paused,
             timeout_cancelled: false,
-            timeout_future: join_handle,
+            timeout_future: None,
+            generation: 0,
         };
 
-        let notifications = self.notifications.try_lock();
-        if let Ok(mut notifications) = notifications {
-            notifications.insert(id, notification);
-            eww_update_notifications(&self.config, &notifications);
+        let old = self.notifications.insert(id, notification);
+        if let Some(old) = &old {
+            if let Some(timeout_future) = &old.timeout_future {
+                timeout_future.abort();
+            }
+        }
+        let was_osd = old.as_ref().is_some_and(|old| old.osd);
+        self.place(id, old.is_some_and(|old| old.visible));
+        if was_osd && !osd {
+            self.update_osd();
+        }
+        if let Some(screen) = screen.filter(|_| !osd) {
+            self.follow_focus(screen);
         }
+        self.update_popups();
         log!("Notification with ID {} created", id);
-        Ok(id)
+        run_hook(
+            &self.config.hooks,
+            HookEvent::Notify,
+            HookNotification::new(id, &self.notifications[&id]),
+        );
+        id
     }
 
-    pub async fn close_notification(&self, id: u32) -> Result<()> {
-        let notifications = self.notifications.try_lock();
-        if let Ok(mut notifications) = notifications {
-            if notifications.remove(&id).is_some() {
-                println!("Notification with ID {} closed", id);
-                eww_update_notifications(&self.config, &notifications);
-                if notifications.is_empty() {
-                    eww_close_notifications(&self.config);
+    fn update_popups(&self) {
+        eww_update_notifications(
+            &self.config,
+            &self.notifications,
+            &self.expanded_groups,
+            self.screen.as_deref(),
+        );
+        self.update_control();
+    }
+
+    /// Renders the OSD notifications. Unlike the popups, this only happens when one of them is
+    /// shown, replaced or closed.
+    fn update_osd(&self) {
+        eww_update_osd(&self.config, &self.notifications);
+    }
+
+    /// Moves the popups to the focused output.
+    fn follow_focus(&mut self, screen: String) {
+        if self.screen.as_ref() != Some(&screen) {
+            log!("Moving popups to {}", screen);
+            // The windows are opened again on the new output by the next update
+            if self.screen.is_some() {
+                eww_close_popups(&self.config);
+            }
+            self.screen = Some(screen);
+        }
+    }
+
+    /// Shows a newly inserted notification or puts it in the overflow queue. `was_visible` is
+    /// whether the notification it replaces was on screen.
+    fn place(&mut self, id: u32, was_visible: bool) {
+        let (osd, critical) = match self.notifications.get(&id) {
+            Some(notif) => (notif.osd, notif.urgency == "critical"),
+            None => return,
+        };
+        if osd || was_visible {
+            // The replacement keeps the id, and with it the position on screen. OSD notifications
+            // have a window of their own, so they never wait in the queue.
+            self.show(id);
+        } else if self.has_free_slot() {
+            self.show(id);
+        } else if critical {
+            // Critical notifications push the newest less urgent popup back into the queue
+            let preempted = self
+                .notifications
+                .iter()
+                .filter(|(_, notif)| notif.visible && !notif.osd && notif.urgency != "critical")
+                .map(|(id, _)| *id)
+                .max();
+            if let Some(preempted) = preempted {
+                log!("Notification with ID {} queued for {}", preempted, id);
+                self.hide(preempted);
+            }
+            self.show(id);
+        } else {
+            log!("Notification with ID {} queued", id);
+        }
+    }
+
+    fn has_free_slot(&self) -> bool {
+        let max = self.config.max_visible_notifications as usize;
+        max == 0
+            || self
+                .notifications
+                .values()
+                .filter(|n| n.visible && !n.osd)
+                .count()
+                < max
+    }
+
+    /// Puts a notification on screen and starts its timeout.
+    fn show(&mut self, id: u32) {
+        if let Some(notif) = self.notifications.get_mut(&id) {
+            notif.visible = true;
+            let osd = notif.osd;
+            self.start_timeout(id);
+            if osd {
+                self.update_osd();
+            }
+        }
+    }
+
+    /// Gives a notification its full timeout again.
+    fn restart_timeout(&mut self, id: u32) {
+        self.stop_timeout(id);
+        if let Some(notif) = self.notifications.get_mut(&id) {
+            notif.remaining = Duration::from_millis(notif.expire_timeout as u64);
+        }
+        self.start_timeout(id);
+    }
+
+    /// Moves a popup back into the queue. Its timeout starts over once it is shown again.
+    fn hide(&mut self, id: u32) {
+        self.stop_timeout(id);
+        if let Some(notif) = self.notifications.get_mut(&id) {
+            notif.visible = false;
+            notif.remaining = Duration::from_millis(notif.expire_timeout as u64);
+        }
+    }
+
+    /// Starts counting down the remaining time of a notification, unless something holds it.
+    fn start_timeout(&mut self, id: u32) {
+        let history_open = self.history_open;
+        let notif = match self.notifications.get_mut(&id) {
+            Some(notif) => notif,
+            None => return,
+        };
+        if notif.expire_timeout == 0
+            || notif.timeout_cancelled
+            || notif.paused
+            || history_open
+            || !notif.visible
+            || notif.timeout_future.is_some()
+        {
+            return;
+        }
+        self.next_generation += 1;
+        let generation = self.next_generation;
+        notif.generation = generation;
+        notif.timeout_started = Some(Instant::now());
+        let remaining = notif.remaining;
+        let tx = self.tx.clone();
+        // Spawn a task to handle timeout
+        notif.timeout_future = Some(tokio::spawn(async move {
+            sleep(remaining).await;
+            let _ = tx.send(DaemonCommand::Expire(id, generation)).await;
+        }));
+    }
+
+    /// Stops the countdown of a notification, keeping the time it has left.
+    fn stop_timeout(&mut self, id: u32) {
+        if let Some(notif) = self.notifications.get_mut(&id) {
+            if let Some(timeout_future) = notif.timeout_future.take() {
+                timeout_future.abort();
+            }
+            if let Some(started) = notif.timeout_started.take() {
+                notif.remaining = notif.remaining.saturating_sub(started.elapsed());
+            }
+        }
+    }
+
+    /// Pauses the timeout of a notification, or of all active ones when `id` is `None`.
+    pub fn pause(&mut self, id: Option<u32>) {
+        let ids: Vec<u32> = match id {
+            Some(id) => vec![id],
+            None => self.notifications.keys().copied().collect(),
+        };
+        for id in ids {
+            self.stop_timeout(id);
+            if let Some(notif) = self.notifications.get_mut(&id) {
+                notif.paused = true;
+            }
+        }
+    }
+
+    /// Resumes the timeout of a notification, or of all active ones when `id` is `None`.
+    pub fn resume(&mut self, id: Option<u32>) {
+        let ids: Vec<u32> = match id {
+            Some(id) => vec![id],
+            None => self.notifications.keys().copied().collect(),
+        };
+        for id in ids {
+            if let Some(notif) = self.notifications.get_mut(&id) {
+                notif.paused = false;
+            }
+            self.start_timeout(id);
+        }
+    }
+
+    fn set_history_open(&mut self, open: bool) {
+        if self.history_open == open {
+            return;
+        }
+        self.history_open = open;
+        let ids: Vec<u32> = self.notifications.keys().copied().collect();
+        for id in ids {
+            if open {
+                self.stop_timeout(id);
+            } else {
+                self.start_timeout(id);
+            }
+        }
+    }
+
+    /// Shows queued notifications while there is room, most urgent and then oldest first.
+    fn fill_slots(&mut self) {
+        while self.has_free_slot() {
+            let next = self
+                .notifications
+                .iter()
+                .filter(|(_, notif)| !notif.visible)
+                .min_by_key(|(id, notif)| (Reverse(urgency_from_str(&notif.urgency)), **id))
+                .map(|(id, _)| *id);
+            match next {
+                Some(id) => self.show(id),
+                None => break,
+            }
+        }
+    }
+
+    fn record_history(&mut self, history_notification: HistoryNotification, replaced: bool) {
+        let id = history_notification.id;
+        let existing = self
+            .notifications_history
+            .iter()
+            .rposition(|hist| replaced && hist.id == id);
+        if let Some(pos) = existing {
+            // A replacement updates the entry of the notification it replaces, which is unread
+            // again
+            self.notifications_history[pos] = history_notification;
+            save_history(&self.notifications_history);
+            self.update_status();
+            return;
+        }
+
+        let icon = history_notification.icon.clone();
+        self.notifications_history.push(history_notification);
+        if trim_history(
+            &mut self.notifications_history,
+            self.config.max_notifications,
+        ) {
+            save_history(&self.notifications_history);
+            self.prune_unused_icons(Some(&icon));
+        } else if let Some(entry) = self.notifications_history.last() {
+            append_history(entry);
+        }
+        self.update_status();
+    }
+
+    /// Removes the saved icons which neither the history nor an active or snoozed notification
+    /// refers to. `pending` is the icon of a notification which is not stored yet.
+    pub fn prune_unused_icons(&self, pending: Option<&str>) {
+        let in_use = self
+            .notifications_history
+            .iter()
+            .map(|hist| hist.icon.as_str())
+            .chain(self.notifications.values().map(|notif| notif.icon.as_str()))
+            .chain(self.snoozed.values().map(|entry| entry.icon.as_str()))
+            .chain(pending);
+        prune_icons(in_use);
+    }
+
+    /// Persists the history after entries were removed and drops the icons nothing uses anymore.
+    fn history_removed(&mut self) {
+        save_history(&self.notifications_history);
+        self.prune_unused_icons(None);
+        self.update_history();
+        self.update_status();
+    }
+
+    fn mark_history_read(&mut self) {
+        if self.notifications_history.iter().all(|hist| hist.read) {
+            return;
+        }
+        for hist in &mut self.notifications_history {
+            hist.read = true;
+        }
+        save_history(&self.notifications_history);
+        self.update_status();
+    }
+
+    /// Publishes the unread count and the DND state to `eww_status_var`, for bar widgets.
+    pub fn update_status(&self) {
+        let unread = self
+            .notifications_history
+            .iter()
+            .filter(|hist| !hist.read)
+            .count();
+        let status = serde_json::json!({
+            "unread": unread,
+            "dnd": self.dnd.enabled,
+            "dnd_missed": self.dnd.missed,
+            "quiet": self.in_quiet_hours(),
+            "snoozed": self.snoozed.len(),
+        });
+        eww_update_value(
+            &self.config,
+            &self.config.eww_status_var,
+            &status.to_string(),
+        );
+        self.update_control();
+    }
+
+    /// Refreshes the properties of the control interface.
+    fn update_control(&self) {
+        let count = |filter: fn(&&Notification) -> bool| {
+            self.notifications.values().filter(filter).count() as u32
+        };
+        let status = ControlStatus {
+            dnd: self.dnd.enabled,
+            dnd_missed: self.dnd.missed,
+            quiet_hours: self.in_quiet_hours(),
+            active: count(|notif| notif.visible && !notif.osd),
+            queued: count(|notif| !notif.visible),
+            snoozed: self.snoozed.len() as u32,
+            unread: self
+                .notifications_history
+                .iter()
+                .filter(|hist| !hist.read)
+                .count() as u32,
+            history: self.notifications_history.len() as u32,
+        };
+        publish_status(&self.connection, &self.control, status);
+    }
+
+    pub fn list(&self) -> Vec<ListedNotification> {
+        let mut list: Vec<ListedNotification> = self
+            .notifications
+            .iter()
+            .map(|(id, notif)| ListedNotification {
+                id: *id,
+                app_name: notif.app_name.clone(),
+                summary: notif.summary.clone(),
+                body: notif.body.clone(),
+                urgency: notif.urgency.clone(),
+                state: if notif.visible { "shown" } else { "queued" }.to_string(),
+            })
+            .chain(self.snoozed.values().map(|entry| ListedNotification {
+                id: entry.id,
+                app_name: entry.app_name.clone(),
+                summary: entry.summary.clone(),
+                body: entry.body.clone(),
+                urgency: entry.urgency.clone(),
+                state: "snoozed".to_string(),
+            }))
+            .collect();
+        list.sort_by_key(|listed| listed.id);
+        list
+    }
+
+    pub fn clear_history(&mut self) {
+        self.notifications_history.clear();
+        self.history_removed();
+    }
+
+    pub fn remove_history(&mut self, id: u32) -> bool {
+        let len = self.notifications_history.len();
+        self.notifications_history.retain(|hist| hist.id != id);
+        if self.notifications_history.len() == len {
+            return false;
+        }
+        self.history_removed();
+        true
+    }
+
+    /// Removes the history of the application of history entry `id`. Like the groups, the
+    /// application is referred to by an entry so its name never ends up in a widget's command.
+    pub fn clear_app_history(&mut self, id: u32) -> String {
+        let app_name = match self.notifications_history.iter().find(|hist| hist.id == id) {
+            Some(hist) => hist.app_name.clone(),
+            None => return format!("No history entry with ID {}", id),
+        };
+        self.notifications_history
+            .retain(|hist| hist.app_name != app_name);
+        self.history_removed();
+        String::new()
+    }
+
+    /// Invokes an action of a notification in the history. The application is identified by the
+    /// id it got back from Notify, so this only works while the same connection is still around.
+    /// Returns an error message for the user, or an empty string on success.
+    pub async fn invoke_history_action(&mut self, id: u32, action: &str) -> String {
+        if self.notifications.contains_key(&id) {
+            self.invoke_action(id, action).await;
+            return String::new();
+        }
+        let hist = match self.notifications_history.iter().find(|hist| hist.id == id) {
+            Some(hist) => hist,
+            None => return format!("No history entry with ID {}", id),
+        };
+        if !hist.actions.iter().any(|(key, _)| key == action) {
+            return format!("History entry {} has no action {}", id, action);
+        }
+        let sender = match &hist.sender {
+            Some(sender) => sender.clone(),
+            None => return format!("The sender of history entry {} is unknown", id),
+        };
+        if !self.name_has_owner(&sender).await {
+            return format!("{} is no longer running", hist.app_name);
+        }
+
+        let dest: Option<&str> = None;
+        let res = self
+            .connection
+            .emit_signal(
+                dest,
+                "/org/freedesktop/Notifications",
+                "org.freedesktop.Notifications",
+                "ActionInvoked",
+                &(id, action),
+            )
+            .await;
+        if let Err(e) = res {
+            log!("Failed to emit ActionInvoked for {}: {}", id, e);
+            return format!("Failed to invoke action {}", action);
+        }
+        run_hook(
+            &self.config.hooks,
+            HookEvent::Action(action.to_string()),
+            HookNotification::from_history(hist),
+        );
+        String::new()
+    }
+
+    async fn name_has_owner(&self, name: &str) -> bool {
+        let name = match zbus::names::BusName::try_from(name) {
+            Ok(name) => name,
+            Err(_) => return false,
+        };
+        let proxy = match zbus::fdo::DBusProxy::new(&self.connection).await {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                log!("Failed to create DBus proxy: {}", e);
+                return false;
+            }
+        };
+        proxy.name_has_owner(name).await.unwrap_or(false)
+    }
+
+    /// The single removal path for active notifications. Removes the notification, updates eww
+    /// and emits NotificationClosed with `reason`. Returns false if the notification was already
+    /// gone, in which case nothing is emitted so every notification is closed exactly once.
+    pub async fn close(&mut self, id: u32, reason: CloseReason) -> bool {
+        let notif = if let Some(notif) = self.notifications.remove(&id) {
+            if let Some(timeout_future) = &notif.timeout_future {
+                timeout_future.abort();
+            }
+            self.removed();
+            if notif.osd {
+                self.update_osd();
+            }
+            notif
+        } else if let Some(entry) = self.snoozed.remove(&id) {
+            self.snoozed_changed();
+            entry.into_notification()
+        } else {
+            return false;
+        };
+
+        log!("Notification with ID {} closed ({:?})", id, reason);
+        emit_notification_closed(&self.connection, id, reason).await;
+        run_hook(
+            &self.config.hooks,
+            HookEvent::Close(reason),
+            HookNotification::new(id, &notif),
+        );
+        true
+    }
+
+    async fn expire(&mut self, id: u32, generation: u64) {
+        let expired = self
+            .notifications
+            .get(&id)
+            .is_some_and(|notif| notif.generation == generation && !notif.timeout_cancelled);
+        if expired {
+            self.close(id, CloseReason::Expired).await;
+        }
+    }
+
+    /// Gets rid of a notification which is not going to be shown. If it was meant to replace an
+    /// active notification, that one is closed instead.
+    async fn discard(&mut self, id: u32, replaced: bool) {
+        // A replaced snoozed notification is already gone, but the sender still has to be told
+        if !(replaced && self.close(id, CloseReason::Undefined).await) {
+            self.close_unshown(id);
+        }
+    }
+
+    /// Updates eww after notifications were taken off the screen, showing queued ones instead.
+    fn removed(&mut self) {
+        self.fill_slots();
+        self.update_popups();
+        if self.notifications.is_empty() {
+            eww_close_notifications(&self.config);
+        }
+    }
+
+    fn snoozed_changed(&self) {
+        save_snoozed(self.snoozed.values());
+        self.update_status();
+    }
+
+    fn schedule_wake(&self, id: u32, until: u64) {
+        let tx = self.tx.clone();
+        let delay = Duration::from_secs(until.saturating_sub(unix_time()));
+        tokio::spawn(async move {
+            sleep(delay).await;
+            let _ = tx.send(DaemonCommand::Wake(id, until)).await;
+        });
+    }
+
+    /// Picks up the notifications snoozed before the daemon was restarted.
+    pub fn restore_snoozed(&mut self) {
+        for entry in load_snoozed() {
+            self.next_id = self.next_id.max(entry.id);
+            self.schedule_wake(entry.id, entry.until);
+            self.snoozed.insert(entry.id, entry);
+        }
+        log!("Restored {} snoozed notifications", self.snoozed.len());
+    }
+
+    /// Hides an active notification for `duration`. The sender is not told, so the notification
+    /// comes back with the same id and actions.
+    pub fn snooze(&mut self, id: u32, duration: Duration) -> String {
+        match self.notifications.get(&id) {
+            Some(notif) if notif.osd => return "OSD notifications can't be snoozed".to_string(),
+            Some(_) => {}
+            None => return format!("No active notification with ID {}", id),
+        }
+        let until = match unix_time().checked_add(duration.as_secs()) {
+            Some(until) => until,
+            None => return format!("Can't snooze for {} seconds", duration.as_secs()),
+        };
+        let mut notif = self.notifications.remove(&id).unwrap();
+        if let Some(timeout_future) = notif.timeout_future.take() {
+            timeout_future.abort();
+        }
+        log!(
+            "Snoozing notification {} for {}",
+            id,
+            format_duration(duration)
+        );
+        self.snoozed
+            .insert(id, SnoozedNotification::new(id, until, notif));
+        self.snoozed_changed();
+        self.schedule_wake(id, until);
+        self.removed();
+        String::new()
+    }
+
+    fn wake(&mut self, id: u32, until: u64) {
+        // A stale timer, the notification was closed or snoozed again in the meantime
+        if self
+            .snoozed
+            .get(&id)
+            .is_none_or(|entry| entry.until != until)
+        {
+            return;
+        }
+        let entry = self.snoozed.remove(&id).unwrap();
+        self.snoozed_changed();
+        log!("Notification {} is back from snooze", id);
+        self.notifications.insert(id, entry.into_notification());
+        self.place(id, false);
+        self.update_popups();
+    }
+
+    /// A human readable overview of the daemon state.
+    pub fn status(&self) -> String {
+        let mut status = self.dnd_status();
+        if !self.quiet_hours.is_empty() {
+            let active = if self.in_quiet_hours() {
+                "active"
+            } else {
+                "inactive"
+            };
+            status.push_str(&format!("\nquiet hours: {}", active));
+        }
+        let unread = self
+            .notifications_history
+            .iter()
+            .filter(|hist| !hist.read)
+            .count();
+        status.push_str(&format!("\nunread: {}", unread));
+        status.push_str(&format!("\nsnoozed: {}", self.snoozed.len()));
+        let mut snoozed: Vec<_> = self.snoozed.values().collect();
+        snoozed.sort_by_key(|entry| entry.until);
+        let now = unix_time();
+        for entry in snoozed {
+            status.push_str(&format!(
+                "\n  {} {}: {} (in {})",
+                entry.id,
+                entry.app_name,
+                entry.summary,
+                format_duration(Duration::from_secs(entry.until.saturating_sub(now)))
+            ));
+        }
+        status
+    }
+
+    /// Reports a notification which was never shown as closed, so that senders waiting for it
+    /// are not left hanging. This happens after Notify has returned the id.
+    fn close_unshown(&self, id: u32) {
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            emit_notification_closed(&connection, id, CloseReason::Undefined).await;
+        });
+    }
+
+    pub fn disable_timeout(&mut self, id: u32) {
+        if let Some(notification) = self.notifications.get_mut(&id) {
+            notification.timeout_cancelled = true;
+            if let Some(timeout_future) = notification.timeout_future.take() {
+                timeout_future.abort();
+            }
+        }
+    }
+
+    /// Invokes an action from a widget, where `inline-reply` opens the reply window.
+    async fn invoke_widget_action(&mut self, id: u32, action: &str) {
+        if action == "inline-reply" {
+            log!("Opening inline reply for notification {}", id);
+            self.open_reply(id);
+            log!("Inline reply for notification {} opened", id);
+        } else {
+            log!("Invoking action {} for notification {}", action, id);
+            self.invoke_action(id, action).await;
+            log!("Invoked action {} for notification {}", action, id);
+        }
+    }
+
+    pub async fn invoke_action(&mut self, id: u32, action: &str) {
+        let notif = match self.notifications.get(&id) {
+            Some(notif) => notif,
+            None => {
+                log!("Notification {} is no longer active", id);
+                return;
+            }
+        };
+        // Links and snoozing are handled by the daemon itself, unless the application uses the
+        // same key
+        if !notif.actions.iter().any(|(key, _)| key == action) {
+            let snooze = action
+                .strip_prefix(SNOOZE_ACTION_PREFIX)
+                .and_then(parse_duration);
+            if let Some(duration) = snooze {
+                self.snooze(id, duration);
+                return;
+            }
+            let link = action
+                .strip_prefix(LINK_ACTION_PREFIX)
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| notif.links.get(index));
+            if let Some((url, _)) = link {
+                let resident = notif.resident;
+                self.open_link(url);
+                if !resident {
+                    self.close(id, CloseReason::Dismissed).await;
                 }
-                let dest: Option<&str> = None;
-                self.connection
-                    .emit_signal(
-                        dest,
-                        "/org/freedesktop/Notifications",
-                        "org.freedesktop.Notifications",
-                        "NotificationClosed",
-                        &(id, 3_u32),
-                    )
-                    .await
-                    .unwrap();
+                return;
             }
         }
-        Ok(())
+        let dest: Option<&str> = None;
+        let res = self
+            .connection
+            .emit_signal(
+                dest,
+                "/org/freedesktop/Notifications",
+                "org.freedesktop.Notifications",
+                "ActionInvoked",
+                &(id, action),
+            )
+            .await;
+        if let Err(e) = res {
+            log!("Failed to emit ActionInvoked for {}: {}", id, e);
+        }
+        if let Some(notif) = self.notifications.get(&id) {
+            run_hook(
+                &self.config.hooks,
+                HookEvent::Action(action.to_string()),
+                HookNotification::new(id, notif),
+            );
+        }
+        if self
+            .notifications
+            .get(&id)
+            .is_some_and(|notif| notif.resident)
+        {
+            log!("Notification {} is resident, keeping it", id);
+        } else {
+            self.close(id, CloseReason::Dismissed).await;
+        }
+    }
+
+    /// What clicking a notification does: invoke its default action if it has one and dismiss it
+    /// otherwise.
+    pub async fn invoke_default(&mut self, id: u32) {
+        let has_default = self
+            .notifications
+            .get(&id)
+            .is_some_and(|notif| notif.actions.iter().any(|(key, _)| key == DEFAULT_ACTION));
+        if has_default {
+            log!("Invoking default action for notification {}", id);
+            self.invoke_action(id, DEFAULT_ACTION).await;
+        } else {
+            log!("Notification {} has no default action, dismissing it", id);
+            self.close(id, CloseReason::Dismissed).await;
+        }
+    }
+
+    fn open_link(&self, url: &str) {
+        log!("Opening link {}", url);
+        let mut command = self.config.link_command.split_whitespace();
+        let program = match command.next() {
+            Some(program) => program,
+            None => return,
+        };
+        let res = std::process::Command::new(program)
+            .args(command)
+            .arg(url)
+            .spawn();
+        match res {
+            // Reap the opener in the background so it does not linger as a zombie
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => {
+                log!("Failed to open link {}: {}", url, e);
+                eprintln!("Failed to open link {}: {}", url, e);
+            }
+        }
+    }
+
+    pub fn open_reply(&mut self, id: u32) {
+        println!("Opening inline reply window");
+        let eww_widget_str = &eww_create_reply_widget(&self.config, id);
+        println!("{}", eww_widget_str);
+        eww_update_value(&self.config, &self.config.eww_reply_text, "");
+        eww_update_value(&self.config, &self.config.eww_reply_var, eww_widget_str);
+        let _ = eww_open_window(&self.config, &self.config.eww_reply_window);
+        self.disable_timeout(id);
+    }
+
+    pub async fn send_reply(&mut self, id: u32, reply: &str) {
+        println!("Replying to notification {}", id);
+        let dest: Option<&str> = None;
+        let res = self
+            .connection
+            .emit_signal(
+                dest,
+                "/org/freedesktop/Notifications",
+                "org.freedesktop.Notifications",
+                "NotificationReplied",
+                &(id, reply),
+            )
+            .await;
+        if let Err(e) = res {
+            log!("Failed to emit NotificationReplied for {}: {}", id, e);
+        }
+        if let Some(notif) = self.notifications.get(&id) {
+            run_hook(
+                &self.config.hooks,
+                HookEvent::Reply(reply.to_string()),
+                HookNotification::new(id, notif),
+            );
+        }
+        self.reply_close(id);
+        self.close(id, CloseReason::Dismissed).await;
+    }
+
+    pub fn reply_close(&mut self, id: u32) {
+        println!("Closing reply window");
+        if let Some(notification) = self.notifications.get_mut(&id) {
+            notification.actions.clear();
+            self.update_popups();
+        }
+        if let Err(e) = eww_close_window(&self.config, &self.config.eww_reply_window) {
+            eprintln!("Failed to close reply window: {}", e);
+        }
+    }
+
+    pub fn update_history(&self) {
+        eww_update_history(
+            &self.config,
+            &self.notifications_history,
+            &self.expanded_history_groups,
+        );
+    }
+
+    pub fn open_history(&mut self) {
+        println!("Getting history");
+        eww_update_and_open_history(
+            &self.config,
+            &self.notifications_history,
+            &self.expanded_history_groups,
+        );
+        self.set_history_open(true);
+        self.mark_history_read();
+    }
+
+    pub fn close_history(&mut self) {
+        println!("Closing history");
+        eww_close_history(&self.config);
+        self.set_history_open(false);
+    }
+
+    /// Toggles the history by the state the daemon keeps, since `eww open --toggle` returns
+    /// before the window is actually open or closed.
+    pub fn toggle_history(&mut self) {
+        println!("Toggling history");
+        if self.history_open {
+            self.close_history();
+        } else {
+            self.open_history();
+        }
+    }
+
+    pub async fn set_dnd(&mut self, enabled: bool) {
+        if self.dnd.enabled == enabled {
+            return;
+        }
+        self.dnd.enabled = enabled;
+        // During quiet hours the count carries on, and is reported once they end
+        let missed = if self.in_quiet_hours() {
+            0
+        } else {
+            std::mem::take(&mut self.dnd.missed)
+        };
+        self.dnd.save();
+        self.update_status();
+        log!("DND {}", if enabled { "enabled" } else { "disabled" });
+
+        if !enabled && missed > 0 {
+            self.notify_missed(missed, "Do Not Disturb", "while Do Not Disturb was on")
+                .await;
+        }
+    }
+
+    async fn notify_missed(&mut self, missed: u32, summary: &str, during: &str) {
+        let body = format!(
+            "You missed {} notification{} {}",
+            missed,
+            if missed == 1 { "" } else { "s" },
+            during
+        );
+        self.notify(NotifyRequest {
+            app_name: "end-rs".to_string(),
+            replaces_id: 0,
+            icon: String::new(),
+            app_icon: String::new(),
+            summary: summary.to_string(),
+            raw_summary: summary.to_string(),
+            raw_body: body.clone(),
+            body,
+            transient: true,
+            received: unix_time(),
+            expire_timeout: -1,
+            ..Default::default()
+        })
+        .await;
+    }
+
+    pub fn in_quiet_hours(&self) -> bool {
+        self.quiet_hours
+            .is_active(chrono::Local::now().naive_local())
+    }
+
+    /// Notices quiet hours starting or ending and checks again at the next boundary. Missed
+    /// notifications are counted like in DND and summed up once quiet hours end, unless DND
+    /// is still on.
+    pub async fn check_quiet_hours(&mut self) {
+        let now = chrono::Local::now().naive_local();
+        let active = self.quiet_hours.is_active(now);
+        if active != self.quiet_active {
+            self.quiet_active = active;
+            self.update_status();
+            log!("Quiet hours {}", if active { "started" } else { "ended" });
+            if !active && !self.dnd.enabled && self.dnd.missed > 0 {
+                let missed = std::mem::take(&mut self.dnd.missed);
+                self.dnd.save();
+                self.update_status();
+                self.notify_missed(missed, "Quiet hours", "during quiet hours")
+                    .await;
+            }
+        }
+
+        if let Some(delay) = self.quiet_hours.next_change(now) {
+            let tx = self.tx.clone();
+            // Local time can jump, so never sleep for more than an hour at once
+            let delay = delay
+                .to_std()
+                .unwrap_or_default()
+                .min(Duration::from_secs(3600));
+            tokio::spawn(async move {
+                sleep(delay).await;
+                let _ = tx.send(DaemonCommand::QuietHours).await;
+            });
+        }
+    }
+
+    /// Expands or collapses the popup group of the application which sent notification `id`.
+    /// `None` toggles it. Groups are referred to by a notification so that application names,
+    /// which come from the sender, never end up in the commands of the widgets.
+    /// Returns an error message for the user, or an empty string on success.
+    pub fn set_group_expanded(&mut self, id: u32, expanded: Option<bool>) -> String {
+        let app_name = match self.notifications.get(&id) {
+            Some(notif) => notif.app_name.clone(),
+            None => return format!("No active notification with ID {}", id),
+        };
+        let expanded = expanded.unwrap_or(!self.expanded_groups.contains(&app_name));
+        if expanded {
+            self.expanded_groups.insert(app_name);
+        } else {
+            self.expanded_groups.remove(&app_name);
+        }
+        self.update_popups();
+        String::new()
+    }
+
+    /// Expands or collapses the history group of the application of history entry `id`.
+    pub fn set_history_group_expanded(&mut self, id: u32, expanded: bool) -> String {
+        let app_name = match self.notifications_history.iter().find(|hist| hist.id == id) {
+            Some(hist) => hist.app_name.clone(),
+            None => return format!("No history entry with ID {}", id),
+        };
+        if expanded {
+            self.expanded_history_groups.insert(app_name);
+        } else {
+            self.expanded_history_groups.remove(&app_name);
+        }
+        self.update_history();
+        String::new()
+    }
+
+    pub fn dnd_status(&self) -> String {
+        if self.dnd.enabled {
+            format!("dnd: on ({} missed)", self.dnd.missed)
+        } else {
+            "dnd: off".to_string()
+        }
+    }
+
+    pub async fn handle_action(&mut self, action: DaemonActions) -> String {
+        let mut response = String::new();
+        match action {
+            DaemonActions::CloseNotification(id) => {
+                log!("Closing notification {}", id);
+                self.close(id, CloseReason::Dismissed).await;
+                log!("Notification {} closed", id);
+            }
+            DaemonActions::OpenHistory => {
+                log!("Opening notification history");
+                self.open_history();
+                log!("Notification history opened");
+            }
+            DaemonActions::CloseHistory => {
+                log!("Closing notification history");
+                self.close_history();
+                log!("Notification history closed");
+            }
+            DaemonActions::ToggleHistory => {
+                log!("Toggling notification history");
+                self.toggle_history();
+                log!("Notification history toggled");
+            }
+            DaemonActions::ActionInvoked(id, action) => {
+                self.invoke_widget_action(id, &action).await;
+            }
+            DaemonActions::ActionAt(id, index) => {
+                let action = self
+                    .notifications
+                    .get(&id)
+                    .and_then(|notif| popup_actions(&self.config, notif).into_iter().nth(index));
+                match action {
+                    Some((action, _)) => self.invoke_widget_action(id, &action).await,
+                    None => response = format!("Notification {} has no action {}", id, index),
+                }
+            }
+            DaemonActions::HistoryClear => {
+                log!("Clearing history");
+                self.clear_history();
+            }
+            DaemonActions::HistoryRemove(id) => {
+                log!("Removing history entry {}", id);
+                if !self.remove_history(id) {
+                    response = format!("No history entry with ID {}", id);
+                }
+            }
+            DaemonActions::HistoryClearApp(id) => {
+                log!("Clearing the history of the application of {}", id);
+                response = self.clear_app_history(id);
+            }
+            DaemonActions::HistoryAction(id, action) => {
+                log!("Invoking action {} of history entry {}", action, id);
+                response = self.invoke_history_action(id, &action).await;
+            }
+            DaemonActions::HistoryActionAt(id, index) => {
+                let action = self
+                    .notifications_history
+                    .iter()
+                    .find(|hist| hist.id == id)
+                    .and_then(|hist| history_actions(hist).get(index).map(|(key, _)| key.clone()));
+                response = match action {
+                    Some(action) => {
+                        log!("Invoking action {} of history entry {}", action, id);
+                        self.invoke_history_action(id, &action).await
+                    }
+                    None => format!("History entry {} has no action {}", id, index),
+                };
+            }
+            DaemonActions::Snooze(id, duration) => {
+                response = self.snooze(id, Duration::from_secs(duration));
+            }
+            DaemonActions::Status => {
+                response = self.status();
+            }
+            DaemonActions::List => {
+                response = self
+                    .list()
+                    .iter()
+                    .map(|listed| {
+                        format!(
+                            "{} {} {}: {} ({})",
+                            listed.id,
+                            listed.state,
+                            listed.app_name,
+                            listed.summary,
+                            listed.urgency
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+            DaemonActions::InvokeDefault(id) => {
+                self.invoke_default(id).await;
+            }
+            DaemonActions::ReplySend(id, reply) => {
+                log!("Sending reply {} for notification {}", reply, id);
+                self.send_reply(id, &reply).await;
+                log!("Sent reply {} for notification {}", reply, id);
+            }
+            DaemonActions::ReplyClose(id) => {
+                log!("Closing reply for notification {}", id);
+                println!("Closing reply for notification {}", id);
+                self.reply_close(id);
+                log!("Closed reply for notification {}", id);
+            }
+            DaemonActions::DndOn => {
+                log!("Enabling DND");
+                self.set_dnd(true).await;
+            }
+            DaemonActions::DndOff => {
+                log!("Disabling DND");
+                self.set_dnd(false).await;
+            }
+            DaemonActions::DndToggle => {
+                log!("Toggling DND");
+                self.set_dnd(!self.dnd.enabled).await;
+            }
+            DaemonActions::DndStatus => {
+                response = self.dnd_status();
+            }
+            DaemonActions::Pause(id) => {
+                log!("Pausing timeout of {:?}", id);
+                self.pause(id);
+            }
+            DaemonActions::Resume(id) => {
+                log!("Resuming timeout of {:?}", id);
+                self.resume(id);
+            }
+            DaemonActions::GroupExpand(id) => {
+                log!("Expanding the group of {}", id);
+                response = self.set_group_expanded(id, Some(true));
+            }
+            DaemonActions::GroupCollapse(id) => {
+                log!("Collapsing the group of {}", id);
+                response = self.set_group_expanded(id, Some(false));
+            }
+            DaemonActions::GroupToggle(id) => {
+                log!("Toggling the group of {}", id);
+                response = self.set_group_expanded(id, None);
+            }
+            DaemonActions::HistoryGroupExpand(id) => {
+                log!("Expanding the history group of {}", id);
+                response = self.set_history_group_expanded(id, true);
+            }
+            DaemonActions::HistoryGroupCollapse(id) => {
+                log!("Collapsing the history group of {}", id);
+                response = self.set_history_group_expanded(id, false);
+            }
+        }
+        response
+    }
+}
+
+/// The org.freedesktop.Notifications interface. It only forwards calls to the state task.
+pub struct NotificationDaemon {
+    pub config: Arc<Config>,
+    pub tx: mpsc::Sender<DaemonCommand>,
+    /// Checked before a notification is parsed, so a flood does not even reach the icon lookups
+    pub limiter: std::sync::Mutex<RateLimiter>,
+}
+
+impl NotificationDaemon {
+    async fn send(&self, command: DaemonCommand) -> Result<()> {
+        self.tx.send(command).await.map_err(|e| {
+            eprintln!("Failed to reach the daemon state: {}", e);
+            zbus::fdo::Error::Failed("Failed to reach the daemon state".to_string())
+        })
+    }
+
+    async fn send_action(&self, action: DaemonActions) -> Result<String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(DaemonCommand::Action(action, reply_tx)).await?;
+        reply_rx
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon state went away".to_string()))
+    }
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl NotificationDaemon {
+    pub async fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: Vec<&str>,
+        hints: HashMap<&str, zvariant::Value<'_>>,
+        expire_timeout: i32,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<u32> {
+        let sender = header.sender().map(|sender| sender.to_string());
+        let allowed =
+            self.limiter
+                .lock()
+                .unwrap()
+                .allow(&self.config.limits, sender.as_deref(), app_name);
+        if !allowed {
+            log!("Notification from {} dropped by the rate limit", app_name);
+            return Err(zbus::fdo::Error::LimitsExceeded(format!(
+                "Too many notifications from {}",
+                app_name
+            )));
+        }
+        let filtered = if self.config.filters.is_empty() {
+            None
+        } else {
+            let notif = FilterNotification {
+                app_name: app_name.to_string(),
+                replaces_id,
+                app_icon: app_icon.to_string(),
+                summary: summary.to_string(),
+                body: body.to_string(),
+                actions: actions
+                    .chunks(2)
+                    .map(|chunk| {
+                        let key = chunk.first().unwrap_or(&"").to_string();
+                        let label = chunk.get(1).unwrap_or(&"").to_string();
+                        (key, label)
+                    })
+                    .collect(),
+                urgency: hint_i32(&hints, "urgency")
+                    .map(|urgency| urgency_to_str(Some(urgency as u8)).to_string()),
+                category: hint_str(&hints, "category"),
+                expire_timeout,
+                hints: hints
+                    .iter()
+                    .filter(|(name, _)| !["image-data", "image_data", "icon_data"].contains(name))
+                    .map(|(name, value)| (name.to_string(), hint_to_json(value)))
+                    .collect(),
+            };
+            match apply_filters(&self.config.filters, notif).await {
+                Some(filtered) => Some(filtered),
+                None => {
+                    let request = NotifyRequest {
+                        app_name: app_name.to_string(),
+                        replaces_id,
+                        sender,
+                        dropped: true,
+                        ..Default::default()
+                    };
+                    let (reply_tx, reply_rx) = oneshot::channel();
+                    self.send(DaemonCommand::Notify(Box::new(request), reply_tx))
+                        .await?;
+                    return reply_rx.await.map_err(|_| {
+                        zbus::fdo::Error::Failed("The daemon state went away".to_string())
+                    });
+                }
+            }
+        };
+        // Filters see the notification before anything is done with it
+        let (app_name, app_icon, summary, body, expire_timeout) = match &filtered {
+            Some(filtered) => (
+                filtered.app_name.as_str(),
+                filtered.app_icon.as_str(),
+                filtered.summary.as_str(),
+                filtered.body.as_str(),
+                filtered.expire_timeout,
+            ),
+            None => (app_name, app_icon, summary, body, expire_timeout),
+        };
+        let actions = match &filtered {
+            Some(filtered) => filtered
+                .actions
+                .iter()
+                .flat_map(|(key, label)| [key.as_str(), label.as_str()])
+                .collect(),
+            None => actions,
+        };
+        let mut request = NotifyRequest::parse(
+            &self.config,
+            sender,
+            app_name,
+            replaces_id,
+            app_icon,
+            summary,
+            body,
+            actions,
+            &hints,
+            expire_timeout,
+        );
+        if let Some(filtered) = &filtered {
+            request.urgency = filtered.urgency.as_deref().and_then(urgency_from_str);
+            request.category = filtered.category.clone().unwrap_or_default();
+        }
+        if !request.is_osd() {
+            request.screen = focused_output(&self.config).await;
+        }
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(DaemonCommand::Notify(Box::new(request), reply_tx))
+            .await?;
+        reply_rx
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon state went away".to_string()))
+    }
+
+    pub async fn close_notification(&self, id: u32) -> Result<()> {
+        self.send(DaemonCommand::Close(id, CloseReason::Closed))
+            .await
     }
 
     pub fn get_capabilities(&self) -> Vec<String> {
-        vec!["body".to_string(), "actions".to_string()]
+        vec![
+            "body".to_string(),
+            "body-markup".to_string(),
+            "body-hyperlinks".to_string(),
+            "actions".to_string(),
+            "action-icons".to_string(),
+            "icon-static".to_string(),
+            "persistence".to_string(),
+            "sound".to_string(),
+        ]
     }
 
     pub fn get_server_information(&self) -> Result<(String, String, String, String)> {
@@ -249,49 +2048,26 @@ impl NotificationDaemon {
     }
 
     pub async fn update_history(&self) -> Result<()> {
-        let history = self.notifications_history.read().await;
-        eww_update_history(&self.config, &history);
-        Ok(())
+        self.send(DaemonCommand::UpdateHistory).await
     }
 
     pub async fn open_history(&self) -> Result<()> {
-        println!("Getting history");
-        let history = self.notifications_history.read().await;
-        eww_update_and_open_history(&self.config, &history);
+        self.send_action(DaemonActions::OpenHistory).await?;
         Ok(())
     }
 
     pub async fn close_history(&self) -> Result<()> {
-        println!("Closing history");
-        eww_close_history(&self.config);
+        self.send_action(DaemonActions::CloseHistory).await?;
         Ok(())
     }
 
     pub async fn toggle_history(&self) -> Result<()> {
-        println!("Toggling history");
-        let history = self.notifications_history.read().await;
-        eww_toggle_history(&self.config, &history);
+        self.send_action(DaemonActions::ToggleHistory).await?;
         Ok(())
     }
 
     pub async fn reply_close(&self, id: u32) -> Result<()> {
-        println!("Closing reply window");
-        let notifications = self.notifications.try_lock();
-        if let Err(e) = notifications {
-            eprintln!("Failed to lock notifications: {}", e);
-            return Err(zbus::fdo::Error::Failed(
-                "Failed to lock notifications".to_string(),
-            ));
-        }
-        let mut notifications = notifications.unwrap();
-        if let Some(notification) = notifications.get_mut(&id) {
-            notification.actions.clear();
-            eww_update_notifications(&self.config, &notifications);
-        }
-        eww_close_window(&self.config, &self.config.eww_reply_window).map_err(|e| {
-            eprintln!("Failed to close reply window: {}", e);
-            zbus::fdo::Error::Failed("Failed to close reply window".to_string())
-        })?;
+        self.send_action(DaemonActions::ReplyClose(id)).await?;
         Ok(())
     }
 
@@ -316,20 +2092,3 @@ impl NotificationDaemon {
         message: &str,
     ) -> zbus::Result<()>;
 }
-
-impl NotificationDaemon {
-    pub async fn disable_timeout(&self, id: u32) -> Result<()> {
-        let notifications = self.notifications.try_lock();
-        if let Err(e) = notifications {
-            eprintln!("Failed to lock notifications: {}", e);
-            return Err(zbus::fdo::Error::Failed(
-                "Failed to lock notifications".to_string(),
-            ));
-        }
-        let mut notifications = notifications.unwrap();
-        if let Some(notification) = notifications.get_mut(&id) {
-            notification.timeout_cancelled = true;
-        }
-        Ok(())
-    }
-}
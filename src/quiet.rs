@@ -0,0 +1,95 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::config::QuietHours;
+use crate::log;
+
+struct QuietRange {
+    /// Weekdays the range starts on. Empty means every day
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+/// The parsed `quiet_hours` of the config.
+#[derive(Default)]
+pub struct QuietSchedule {
+    ranges: Vec<QuietRange>,
+}
+
+fn parse_range(range: &QuietHours) -> Result<QuietRange, String> {
+    let days = range
+        .days
+        .iter()
+        .map(|day| {
+            day.parse::<Weekday>()
+                .map_err(|_| format!("invalid day {:?}", day))
+        })
+        .collect::<Result<_, _>>()?;
+    let parse_time = |time: &str| {
+        NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("invalid time {:?}", time))
+    };
+    Ok(QuietRange {
+        days,
+        start: parse_time(&range.start)?,
+        end: parse_time(&range.end)?,
+    })
+}
+
+impl QuietRange {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        if self.start <= self.end {
+            self.starts_on(now.weekday()) && self.start <= time && time < self.end
+        } else {
+            // Past midnight the range belongs to the day it started on
+            (self.starts_on(now.weekday()) && time >= self.start)
+                || (self.starts_on(now.weekday().pred()) && time < self.end)
+        }
+    }
+}
+
+impl QuietSchedule {
+    pub fn new(ranges: &[QuietHours]) -> Self {
+        let ranges = ranges
+            .iter()
+            .enumerate()
+            .filter_map(|(i, range)| match parse_range(range) {
+                Ok(range) => Some(range),
+                Err(e) => {
+                    log!("Ignoring quiet hours {}: {}", i, e);
+                    eprintln!("Ignoring quiet hours {}: {}", i, e);
+                    None
+                }
+            })
+            .collect();
+        QuietSchedule { ranges }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Whether `now`, in local time, falls into any of the ranges.
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.ranges.iter().any(|range| range.contains(now))
+    }
+
+    /// The time until the next start or end of a range, so the daemon can check again then.
+    pub fn next_change(&self, now: NaiveDateTime) -> Option<Duration> {
+        self.ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end])
+            .map(|time| {
+                let mut at = now.date().and_time(time);
+                if at <= now {
+                    at += Duration::days(1);
+                }
+                at - now
+            })
+            .min()
+    }
+}
@@ -0,0 +1,152 @@
+use regex::Regex;
+
+use crate::config::{NotificationWindow, Rule};
+use crate::log;
+
+/// The notification fields a rule can match on.
+pub struct RuleInput<'a> {
+    pub app_name: &'a str,
+    pub summary: &'a str,
+    pub body: &'a str,
+    pub category: &'a str,
+    pub urgency: &'a str,
+}
+
+/// The combined overrides of every rule matching a notification. Later rules take precedence
+/// over earlier ones.
+#[derive(Default, Debug)]
+pub struct RuleOutcome {
+    pub urgency: Option<u8>,
+    pub timeout: Option<u32>,
+    pub window: Option<NotificationWindow>,
+    pub var: Option<String>,
+    pub widget: Option<String>,
+    pub skip_history: bool,
+    pub transient: Option<bool>,
+    pub drop: bool,
+}
+
+enum RuleField {
+    AppName,
+    Summary,
+    Body,
+    Category,
+    Urgency,
+}
+
+struct CompiledRule {
+    matchers: Vec<(RuleField, Regex)>,
+    rule: Rule,
+}
+
+#[derive(Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+pub fn urgency_from_str(urgency: &str) -> Option<u8> {
+    match urgency {
+        "low" => Some(0),
+        "normal" => Some(1),
+        "critical" => Some(2),
+        _ => None,
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+fn compile_rule(rule: &Rule) -> Result<CompiledRule, regex::Error> {
+    let fields = [
+        (RuleField::AppName, &rule.app_name),
+        (RuleField::Summary, &rule.summary),
+        (RuleField::Body, &rule.body),
+        (RuleField::Category, &rule.category),
+        (RuleField::Urgency, &rule.urgency),
+    ];
+    let mut matchers = Vec::new();
+    for (field, pattern) in fields {
+        if let Some(pattern) = pattern {
+            let regex = if rule.regex {
+                Regex::new(pattern)?
+            } else {
+                Regex::new(&glob_to_regex(pattern))?
+            };
+            matchers.push((field, regex));
+        }
+    }
+    Ok(CompiledRule {
+        matchers,
+        rule: rule.clone(),
+    })
+}
+
+impl RuleSet {
+    pub fn new(rules: &[Rule]) -> Self {
+        let rules = rules
+            .iter()
+            .enumerate()
+            .filter_map(|(i, rule)| match compile_rule(rule) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    log!("Ignoring rule {}: {}", i, e);
+                    eprintln!("Ignoring rule {}: {}", i, e);
+                    None
+                }
+            })
+            .collect();
+        RuleSet { rules }
+    }
+
+    pub fn evaluate(&self, input: &RuleInput) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+        for compiled in &self.rules {
+            let matches = compiled.matchers.iter().all(|(field, regex)| {
+                let value = match field {
+                    RuleField::AppName => input.app_name,
+                    RuleField::Summary => input.summary,
+                    RuleField::Body => input.body,
+                    RuleField::Category => input.category,
+                    RuleField::Urgency => input.urgency,
+                };
+                regex.is_match(value)
+            });
+            if !matches {
+                continue;
+            }
+
+            let rule = &compiled.rule;
+            if let Some(urgency) = &rule.set_urgency {
+                outcome.urgency = urgency_from_str(urgency).or(outcome.urgency);
+            }
+            if rule.timeout.is_some() {
+                outcome.timeout = rule.timeout;
+            }
+            if rule.window.is_some() {
+                outcome.window = rule.window.clone();
+            }
+            if rule.var.is_some() {
+                outcome.var = rule.var.clone();
+            }
+            if rule.widget.is_some() {
+                outcome.widget = rule.widget.clone();
+            }
+            if rule.transient.is_some() {
+                outcome.transient = rule.transient;
+            }
+            outcome.skip_history |= rule.skip_history;
+            outcome.drop |= rule.drop;
+        }
+        outcome
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+
+use crate::config::NotificationWindow;
+use crate::log;
+use crate::notifdaemon::Notification;
+use crate::utils::get_state_dir;
+
+/// A notification hidden until `until`, after which it is shown again with the same id. The
+/// sender is not told about the snooze, so its actions keep working once it is back.
+#[derive(Serialize, Deserialize)]
+pub struct SnoozedNotification {
+    pub id: u32,
+    /// Unix timestamp in seconds
+    pub until: u64,
+    pub app_name: String,
+    pub icon: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub urgency: String,
+    pub actions: Vec<(String, String)>,
+    pub links: Vec<(String, String)>,
+    pub window: Option<NotificationWindow>,
+    #[serde(default)]
+    pub var: Option<String>,
+    pub widget: Option<String>,
+    #[serde(default)]
+    pub value: Option<i32>,
+    #[serde(default)]
+    pub stack_tag: Option<String>,
+    pub resident: bool,
+    pub action_icons: bool,
+    pub position: Option<(i32, i32)>,
+    pub category: String,
+    pub desktop_entry: Option<String>,
+    pub received: u64,
+    pub sender: Option<String>,
+    pub hints: serde_json::Map<String, serde_json::Value>,
+    pub expire_timeout: i32,
+}
+
+impl SnoozedNotification {
+    pub fn new(id: u32, until: u64, notif: Notification) -> Self {
+        SnoozedNotification {
+            id,
+            until,
+            app_name: notif.app_name,
+            icon: notif.icon,
+            app_icon: notif.app_icon,
+            summary: notif.summary,
+            body: notif.body,
+            urgency: notif.urgency,
+            actions: notif.actions,
+            links: notif.links,
+            window: notif.window,
+            var: notif.var,
+            widget: notif.widget,
+            value: notif.value,
+            stack_tag: notif.stack_tag,
+            resident: notif.resident,
+            action_icons: notif.action_icons,
+            position: notif.position,
+            category: notif.category,
+            desktop_entry: notif.desktop_entry,
+            received: notif.received,
+            sender: notif.sender,
+            hints: notif.hints,
+            expire_timeout: notif.expire_timeout,
+        }
+    }
+
+    /// Turns the entry back into a notification, waiting to be shown with a fresh timeout.
+    pub fn into_notification(self) -> Notification {
+        Notification {
+            app_name: self.app_name,
+            icon: self.icon,
+            app_icon: self.app_icon,
+            summary: self.summary,
+            body: self.body,
+            urgency: self.urgency,
+            actions: self.actions,
+            links: self.links,
+            window: self.window,
+            var: self.var,
+            widget: self.widget,
+            value: self.value,
+            stack_tag: self.stack_tag,
+            osd: false,
+            resident: self.resident,
+            action_icons: self.action_icons,
+            position: self.position,
+            category: self.category,
+            desktop_entry: self.desktop_entry,
+            received: self.received,
+            sender: self.sender,
+            hints: self.hints,
+            expire_timeout: self.expire_timeout,
+            visible: false,
+            remaining: Duration::from_millis(self.expire_timeout as u64),
+            timeout_started: None,
+            paused: false,
+            timeout_cancelled: false,
+            timeout_future: None,
+            generation: 0,
+        }
+    }
+}
+
+fn snoozed_path() -> String {
+    format!("{}/snoozed.json", get_state_dir())
+}
+
+pub fn load_snoozed() -> Vec<SnoozedNotification> {
+    let contents = match fs::read_to_string(snoozed_path()) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+    match serde_json::from_str(&contents) {
+        Ok(snoozed) => snoozed,
+        Err(e) => {
+            log!("Failed to parse snoozed notifications: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn save_snoozed<'a>(snoozed: impl Iterator<Item = &'a SnoozedNotification>) {
+    let snoozed: Vec<_> = snoozed.collect();
+    let contents = serde_json::to_string(&snoozed).unwrap();
+    let path = snoozed_path();
+    let tmp_path = format!("{}.tmp", path);
+    let res = fs::write(&tmp_path, contents).and_then(|_| fs::rename(&tmp_path, &path));
+    if let Err(e) = res {
+        log!("Failed to save snoozed notifications: {}", e);
+    }
+}
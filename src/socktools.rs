@@ -4,32 +4,86 @@ use std::path::Path;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot, watch};
 use zbus::conn::Builder;
 use zbus::fdo::Result;
-use zbus::Connection;
 
 use crate::config::Config;
-use crate::ewwface::{eww_create_reply_widget, eww_open_window, eww_update_value};
+use crate::control::{ControlInterface, ControlStatus, CONTROL_NAME, CONTROL_PATH};
+use crate::history::load_history;
 use crate::log;
-use crate::notifdaemon::NotificationDaemon;
+use crate::notifdaemon::{run_state, DaemonCommand, DaemonState, DndState, NotificationDaemon};
+use crate::quiet::QuietSchedule;
+use crate::rules::RuleSet;
+use crate::utils::parse_duration;
 
 #[derive(Serialize, Deserialize)]
-enum DaemonActions {
+pub enum DaemonActions {
     CloseNotification(u32),
     OpenHistory,
     CloseHistory,
     ToggleHistory,
     ActionInvoked(u32, String),
+    /// Invokes the action at the given index of a popup's `actions`, which is what widgets use
+    ActionAt(u32, usize),
+    /// Invokes the `default` action, or just dismisses the notification if it has none
+    InvokeDefault(u32),
     ReplySend(u32, String),
     ReplyClose(u32),
+    DndOn,
+    DndOff,
+    DndToggle,
+    DndStatus,
+    /// Expands the popup group of the application which sent the given notification
+    GroupExpand(u32),
+    GroupCollapse(u32),
+    GroupToggle(u32),
+    /// Expands the history group of the application of the given history entry
+    HistoryGroupExpand(u32),
+    HistoryGroupCollapse(u32),
+    HistoryClear,
+    HistoryRemove(u32),
+    /// Removes the history entries of the application of the given history entry
+    HistoryClearApp(u32),
+    HistoryAction(u32, String),
+    /// Invokes the action at the given index of a history entry's `actions`
+    HistoryActionAt(u32, usize),
+    /// Pauses the timeout of a notification, or of all of them when `None`
+    Pause(Option<u32>),
+    /// Snoozes a notification for the given number of seconds
+    Snooze(u32, u64),
+    Status,
+    /// Lists the active and snoozed notifications
+    List,
+    Resume(Option<u32>),
 }
 
-async fn handle_connection(stream: UnixStream, tx: mpsc::Sender<String>) {
-    let mut reader = BufReader::new(stream);
+async fn handle_connection(stream: UnixStream, tx: mpsc::Sender<DaemonCommand>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
     let mut line = String::new();
-    while reader.read_line(&mut line).await.unwrap() > 0 {
-        tx.send(line.clone()).await.unwrap();
+    while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+        println!("Received: {}", line);
+        let response = match serde_json::from_str::<DaemonActions>(&line) {
+            Ok(action) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if tx
+                    .send(DaemonCommand::Action(action, reply_tx))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                reply_rx.await.unwrap_or_default()
+            }
+            Err(e) => {
+                log!("Invalid message {}: {}", line, e);
+                format!("Invalid message: {}", e)
+            }
+        };
+        // Responses are sent as JSON strings, so multi-line responses still take one line
+        let response = serde_json::to_string(&response).unwrap();
+        let _ = writer.write_all(format!("{}\n", response).as_bytes()).await;
         line.clear();
     }
 }
@@ -48,127 +102,58 @@ pub async fn run_daemon(cfg: Config) -> Result<()> {
         zbus::fdo::Error::Failed("Failed to bind to socket".to_string())
     })?;
 
-    let (tx, mut rx) = mpsc::channel::<String>(100);
+    let (tx, rx) = mpsc::channel::<DaemonCommand>(100);
     let cfg = Arc::new(cfg);
 
-    // Initialize daemon-specific structures
-    let connection = Connection::session().await?;
     let daemon = NotificationDaemon {
-        notifications: Default::default(),
-        notifications_history: Default::default(),
         config: Arc::clone(&cfg),
-        next_id: 0,
-        connection,
+        tx: tx.clone(),
+        limiter: Default::default(),
+    };
+
+    let (control, control_status) = watch::channel(ControlStatus::default());
+    let control_interface = ControlInterface {
+        tx: tx.clone(),
+        status: control_status,
     };
 
     let conn = Builder::session()?
         .name("org.freedesktop.Notifications")?
+        .name(CONTROL_NAME)?
         .serve_at("/org/freedesktop/Notifications", daemon)?
+        .serve_at(CONTROL_PATH, control_interface)?
         .build()
         .await?;
 
-    tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            let iface_ref = conn
-                .object_server()
-                .interface::<_, NotificationDaemon>("/org/freedesktop/Notifications")
-                .await
-                .unwrap();
-
-            let iface = iface_ref.get_mut().await;
-            println!("Received: {}", message);
-            let message: DaemonActions = serde_json::from_str(&message).unwrap();
-            let dest: Option<&str> = None;
-
-            match message {
-                DaemonActions::CloseNotification(id) => {
-                    log!("Closing notification {}", id);
-                    iface.close_notification(id).await.unwrap();
-                    log!("Notification {} closed", id);
-                }
-                DaemonActions::OpenHistory => {
-                    log!("Opening notification history");
-                    iface.open_history().await.unwrap();
-                    log!("Notification history opened");
-                }
-                DaemonActions::CloseHistory => {
-                    log!("Closing notification history");
-                    iface.close_history().await.unwrap();
-                    log!("Notification history closed");
-                }
-                DaemonActions::ToggleHistory => {
-                    log!("Toggling notification history");
-                    iface.toggle_history().await.unwrap();
-                    log!("Notification history toggled");
-                }
-                DaemonActions::ActionInvoked(id, action) => {
-                    if action == "inline-reply" {
-                        log!("Opening inline reply for notification {}", id);
-                        println!("Opening inline reply window");
-                        let eww_widget_str = &eww_create_reply_widget(&cfg, id);
-                        println!("{}", eww_widget_str);
-                        eww_update_value(&cfg, &cfg.eww_reply_text, "");
-                        eww_update_value(&cfg, &cfg.eww_reply_var, eww_widget_str);
-                        let _ = eww_open_window(&cfg, &cfg.eww_reply_window);
-                        iface.disable_timeout(id).await.unwrap();
-                        log!("Inline reply for notification {} opened", id);
-                    } else {
-                        log!("Invoking action {} for notification {}", action, id);
-                        conn.emit_signal(
-                            dest,
-                            "/org/freedesktop/Notifications",
-                            "org.freedesktop.Notifications",
-                            "ActionInvoked",
-                            &(id, &action),
-                        )
-                        .await
-                        .unwrap();
-                        conn.emit_signal(
-                            dest,
-                            "/org/freedesktop/Notifications",
-                            "org.freedesktop.Notifications",
-                            "NotificationClosed",
-                            &(id, 3_u32),
-                        )
-                        .await
-                        .unwrap();
-                        log!("Invoked action {} for notification {}", action, id);
-                    }
-                }
-                DaemonActions::ReplySend(id, reply) => {
-                    log!("Sending reply {} for notification {}", reply, id);
-                    println!("Replying to notification {}", id);
-                    conn.emit_signal(
-                        dest,
-                        "/org/freedesktop/Notifications",
-                        "org.freedesktop.Notifications",
-                        "NotificationReplied",
-                        &(id, &reply),
-                    )
-                    .await
-                    .unwrap();
-                    iface.reply_close(id).await.unwrap();
-                    iface.close_notification(id).await.unwrap();
-                    log!("Sent reply {} for notification {}", reply, id);
-                }
-                DaemonActions::ReplyClose(id) => {
-                    log!("Closing reply for notification {}", id);
-                    println!("Closing reply for notification {}", id);
-                    conn.call_method(
-                        Some("org.freedesktop.Notifications"),
-                        "/org/freedesktop/Notifications",
-                        Some("org.freedesktop.Notifications"),
-                        "ReplyClose",
-                        &(id),
-                    )
-                    .await
-                    .unwrap();
-                    iface.reply_close(id).await.unwrap();
-                    log!("Closed reply for notification {}", id);
-                }
-            };
-        }
-    });
+    // Initialize daemon-specific structures
+    let history = load_history(cfg.max_notifications);
+    // Continue numbering after the persisted history so ids stay unique across restarts
+    let next_id = history.iter().map(|hist| hist.id).max().unwrap_or(0);
+    let mut state = DaemonState {
+        config: Arc::clone(&cfg),
+        notifications: Default::default(),
+        notifications_history: history,
+        connection: conn,
+        next_id,
+        next_generation: 0,
+        dnd: DndState::load(),
+        rules: RuleSet::new(&cfg.rules),
+        expanded_groups: Default::default(),
+        expanded_history_groups: Default::default(),
+        history_open: false,
+        snoozed: Default::default(),
+        quiet_hours: QuietSchedule::new(&cfg.quiet_hours),
+        quiet_active: false,
+        screen: None,
+        control,
+        tx: tx.clone(),
+    };
+    state.restore_snoozed();
+    // Only now that the snoozed notifications are back is it known which icons are in use
+    state.prune_unused_icons(None);
+    state.update_status();
+    state.check_quiet_hours().await;
+    tokio::spawn(run_state(state, rx));
 
     loop {
         let (stream, _) = listener.accept().await.map_err(|e| {
@@ -182,6 +167,17 @@ pub async fn run_daemon(cfg: Config) -> Result<()> {
     }
 }
 
+/// Parses the `--index <n>` form of the action commands.
+fn action_index(args: &[String]) -> Result<Option<usize>> {
+    match args {
+        [flag, index] if flag == "--index" => index
+            .parse::<usize>()
+            .map(Some)
+            .map_err(|_| zbus::fdo::Error::Failed(format!("Invalid action index {}", index))),
+        _ => Ok(None),
+    }
+}
+
 pub async fn send_message(args: Vec<String>) -> Result<()> {
     let path = "/tmp/rust_ipc_socket";
 
@@ -212,6 +208,51 @@ pub async fn send_message(args: Vec<String>) -> Result<()> {
                     "open" => DaemonActions::OpenHistory,
                     "close" => DaemonActions::CloseHistory,
                     "toggle" => DaemonActions::ToggleHistory,
+                    "expand" | "collapse" => {
+                        if args.len() < 3 {
+                            return Err(zbus::fdo::Error::Failed(
+                                "Invalid command to history".to_string(),
+                            ));
+                        }
+                        let id = args[2].parse::<u32>().map_err(|_| {
+                            zbus::fdo::Error::Failed("Invalid history ID".to_string())
+                        })?;
+                        if args[1] == "expand" {
+                            DaemonActions::HistoryGroupExpand(id)
+                        } else {
+                            DaemonActions::HistoryGroupCollapse(id)
+                        }
+                    }
+                    "clear" => DaemonActions::HistoryClear,
+                    "remove" | "clear-app" => {
+                        if args.len() < 3 {
+                            return Err(zbus::fdo::Error::Failed(
+                                "Invalid command to history".to_string(),
+                            ));
+                        }
+                        let id = args[2].parse::<u32>().map_err(|_| {
+                            zbus::fdo::Error::Failed("Invalid history ID".to_string())
+                        })?;
+                        if args[1] == "remove" {
+                            DaemonActions::HistoryRemove(id)
+                        } else {
+                            DaemonActions::HistoryClearApp(id)
+                        }
+                    }
+                    "action" => {
+                        if args.len() < 4 {
+                            return Err(zbus::fdo::Error::Failed(
+                                "Invalid command to history".to_string(),
+                            ));
+                        }
+                        let id = args[2].parse::<u32>().map_err(|_| {
+                            zbus::fdo::Error::Failed("Invalid history ID".to_string())
+                        })?;
+                        match action_index(&args[3..])? {
+                            Some(index) => DaemonActions::HistoryActionAt(id, index),
+                            None => DaemonActions::HistoryAction(id, args[3].clone()),
+                        }
+                    }
                     _ => {
                         return Err(zbus::fdo::Error::Failed("Invalid command".to_string()));
                     }
@@ -223,7 +264,93 @@ pub async fn send_message(args: Vec<String>) -> Result<()> {
                         "Invalid command to action".to_string(),
                     ));
                 }
-                DaemonActions::ActionInvoked(args[1].parse::<u32>().unwrap(), args[2].to_string())
+                let id = args[1]
+                    .parse::<u32>()
+                    .map_err(|_| zbus::fdo::Error::Failed("Invalid notification ID".to_string()))?;
+                match action_index(&args[2..])? {
+                    Some(index) => DaemonActions::ActionAt(id, index),
+                    None => DaemonActions::ActionInvoked(id, args[2].to_string()),
+                }
+            }
+            "default" => {
+                if args.len() < 2 {
+                    return Err(zbus::fdo::Error::Failed(
+                        "Invalid command to default".to_string(),
+                    ));
+                }
+                let id = args[1]
+                    .parse::<u32>()
+                    .map_err(|_| zbus::fdo::Error::Failed("Invalid notification ID".to_string()))?;
+                DaemonActions::InvokeDefault(id)
+            }
+            "group" => {
+                if args.len() < 3 {
+                    return Err(zbus::fdo::Error::Failed(
+                        "Invalid command to group".to_string(),
+                    ));
+                }
+                let id = args[2]
+                    .parse::<u32>()
+                    .map_err(|_| zbus::fdo::Error::Failed("Invalid notification ID".to_string()))?;
+                match args[1].as_str() {
+                    "expand" => DaemonActions::GroupExpand(id),
+                    "collapse" => DaemonActions::GroupCollapse(id),
+                    "toggle" => DaemonActions::GroupToggle(id),
+                    _ => {
+                        return Err(zbus::fdo::Error::Failed("Invalid command".to_string()));
+                    }
+                }
+            }
+            "dnd" => {
+                if args.len() < 2 {
+                    return Err(zbus::fdo::Error::Failed(
+                        "Invalid command to dnd".to_string(),
+                    ));
+                }
+                match args[1].as_str() {
+                    "on" => DaemonActions::DndOn,
+                    "off" => DaemonActions::DndOff,
+                    "toggle" => DaemonActions::DndToggle,
+                    "status" => DaemonActions::DndStatus,
+                    _ => {
+                        return Err(zbus::fdo::Error::Failed("Invalid command".to_string()));
+                    }
+                }
+            }
+            "snooze" => {
+                if args.len() < 3 {
+                    return Err(zbus::fdo::Error::Failed(
+                        "Invalid command to snooze".to_string(),
+                    ));
+                }
+                let id = args[1]
+                    .parse::<u32>()
+                    .map_err(|_| zbus::fdo::Error::Failed("Invalid notification ID".to_string()))?;
+                let duration = parse_duration(&args[2]).ok_or_else(|| {
+                    zbus::fdo::Error::Failed(format!("Invalid duration {}", args[2]))
+                })?;
+                DaemonActions::Snooze(id, duration.as_secs())
+            }
+            "status" => DaemonActions::Status,
+            "list" => DaemonActions::List,
+            "pause" | "resume" => {
+                if args.len() < 2 {
+                    return Err(zbus::fdo::Error::Failed(format!(
+                        "Invalid command to {}",
+                        args[0]
+                    )));
+                }
+                let id = match args[1].as_str() {
+                    "all" => None,
+                    id => Some(id.parse::<u32>().map_err(|_| {
+                        zbus::fdo::Error::Failed("Invalid notification ID".to_string())
+                    })?),
+                };
+                if args[0] == "pause" {
+                    DaemonActions::Pause(id)
+                } else {
+                    DaemonActions::Resume(id)
+                }
             }
             "reply" => {
                 if args.len() < 3 {
@@ -252,24 +379,47 @@ pub async fn send_message(args: Vec<String>) -> Result<()> {
             }
         };
 
+        // Bar scripts parse the output of queries, so they print nothing but the response
+        let query = matches!(
+            message,
+            DaemonActions::DndStatus | DaemonActions::Status | DaemonActions::List
+        );
         let message = serde_json::to_string(&message).map_err(|e| {
             eprintln!("Failed to serialize message: {}", e);
             zbus::fdo::Error::Failed("Failed to serialize message".to_string())
         })?;
 
-        println!("Sending message {:?}", message);
-        let message = message.as_bytes();
+        if !query {
+            println!("Sending message {:?}", message);
+        }
+        let message = format!("{}\n", message);
 
-        stream.write_all(message).await.map_err(|e| {
+        stream.write_all(message.as_bytes()).await.map_err(|e| {
             eprintln!("Failed to write to stream: {}", e);
             zbus::fdo::Error::Failed("Failed to write to stream".to_string())
         })?;
-        println!("Message sent");
+        if !query {
+            println!("Message sent");
+        }
+
+        let mut response = String::new();
+        BufReader::new(&mut stream)
+            .read_line(&mut response)
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to read from stream: {}", e);
+                zbus::fdo::Error::Failed("Failed to read from stream".to_string())
+            })?;
+        let response: String = serde_json::from_str(response.trim_end()).unwrap_or_default();
+        if !response.is_empty() {
+            println!("{}", response);
+        }
+        if !query {
+            println!("Exiting");
+        }
     } else {
         eprintln!("Failed to connect to the daemon.");
     }
 
-    println!("Exiting");
-
     Ok(())
 }
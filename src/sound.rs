@@ -0,0 +1,134 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::SoundConfig;
+use crate::log;
+
+/// What a notification asked to play, or the default for its urgency.
+pub enum Sound {
+    File(String),
+    Name(String),
+}
+
+const SOUND_EXTENSIONS: [&str; 3] = ["oga", "ogg", "wav"];
+
+/// `$XDG_DATA_HOME/sounds` followed by the `sounds` dir of every entry of `$XDG_DATA_DIRS`.
+fn sound_dirs() -> Vec<PathBuf> {
+    let data_home = env::var("XDG_DATA_HOME")
+        .unwrap_or_else(|_| format!("{}/.local/share", env::var("HOME").unwrap_or_default()));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+    std::iter::once(data_home.as_str())
+        .chain(data_dirs.split(':'))
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join("sounds"))
+        .collect()
+}
+
+/// Reads the sub directories and the parents of a theme from its `index.theme`.
+fn theme_index(dirs: &[PathBuf], theme: &str) -> (Vec<String>, Vec<String>) {
+    let index = dirs
+        .iter()
+        .find_map(|dir| std::fs::read_to_string(dir.join(theme).join("index.theme")).ok())
+        .unwrap_or_default();
+    let list = |key: &str| -> Vec<String> {
+        index
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.trim_start().strip_prefix('='))
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim().to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let mut subdirs = list("Directories");
+    if subdirs.is_empty() {
+        subdirs.push(String::from("stereo"));
+    }
+    (subdirs, list("Inherits"))
+}
+
+/// Looks a sound name up following the freedesktop sound theme spec. Unknown names fall back to
+/// their less specific forms, so `message-new-email` can be played as `message-new`, and every
+/// theme falls back to the `freedesktop` theme.
+pub fn find_sound(name: &str, theme: &str) -> Option<PathBuf> {
+    let dirs = sound_dirs();
+    // The theme and its parents, breadth first
+    let mut themes = vec![(theme.to_string(), theme_index(&dirs, theme))];
+    let mut i = 0;
+    while i < themes.len() {
+        for parent in themes[i].1 .1.clone() {
+            if !themes.iter().any(|(theme, _)| *theme == parent) {
+                let index = theme_index(&dirs, &parent);
+                themes.push((parent, index));
+            }
+        }
+        i += 1;
+    }
+    if !themes.iter().any(|(theme, _)| theme == "freedesktop") {
+        themes.push((
+            String::from("freedesktop"),
+            theme_index(&dirs, "freedesktop"),
+        ));
+    }
+
+    let mut name = name;
+    loop {
+        for (theme, (subdirs, _)) in &themes {
+            for dir in &dirs {
+                for subdir in subdirs {
+                    for ext in SOUND_EXTENSIONS {
+                        let path = dir
+                            .join(theme)
+                            .join(subdir)
+                            .join(format!("{}.{}", name, ext));
+                        if path.is_file() {
+                            return Some(path);
+                        }
+                    }
+                }
+            }
+        }
+        name = &name[..name.rfind('-')?];
+    }
+}
+
+/// Plays a sound with the configured player, without waiting for it.
+pub fn play_sound(config: &SoundConfig, sound: Sound) {
+    let mut command = config.command.split_whitespace().map(str::to_string);
+    let program = match command.next() {
+        Some(program) => program,
+        None => return,
+    };
+    let args: Vec<String> = command.collect();
+    let theme = config.theme.clone();
+    // Looking the sound up touches the disk, and the player is reaped once it is done
+    std::thread::spawn(move || {
+        let path = match sound {
+            Sound::File(path) => PathBuf::from(path.strip_prefix("file://").unwrap_or(&path)),
+            Sound::Name(name) => match find_sound(&name, &theme) {
+                Some(path) => path,
+                None => {
+                    log!("Sound {} not found in theme {}", name, theme);
+                    return;
+                }
+            },
+        };
+        log!("Playing sound {}", path.display());
+        match Command::new(&program).args(&args).arg(&path).spawn() {
+            Ok(mut child) => {
+                let _ = child.wait();
+            }
+            Err(e) => {
+                log!("Failed to play sound {}: {}", path.display(), e);
+                eprintln!("Failed to play sound {}: {}", path.display(), e);
+            }
+        }
+    });
+}
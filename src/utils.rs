@@ -1,4 +1,5 @@
 use icon_loader::IconLoader;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::{fs, io::Write, path::Path};
 use zvariant::{Structure, Value};
 
@@ -9,6 +10,14 @@ use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
 
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0)
+}
+
 pub fn find_icon(icon_name: &str, config: &Config) -> Option<String> {
     // Check whether the icon needs to be searched
     log!("Icon name: {}", icon_name);
@@ -73,46 +82,116 @@ pub fn find_icon(icon_name: &str, config: &Config) -> Option<String> {
     }
 }
 
-pub fn save_icon(icon_data: &Structure, id: u32) -> Option<String> {
-    let parent_dir = "/tmp/end-data";
-    if !Path::new(&parent_dir).exists() {
-        fs::create_dir_all(parent_dir).unwrap();
-    }
-    let icon_path = format!("{}/{}.png", parent_dir, id);
-    let width: Result<i32, zvariant::Error> = icon_data.fields()[0].try_clone().unwrap().try_into();
-    let height: Result<i32, zvariant::Error> =
-        icon_data.fields()[1].try_clone().unwrap().try_into();
-    let _rowstride: Result<i32, zvariant::Error> =
-        icon_data.fields()[2].try_clone().unwrap().try_into();
-    let _has_alpha: Result<bool, zvariant::Error> =
-        icon_data.fields()[3].try_clone().unwrap().try_into();
-    let _bits_per_sample: Result<i32, zvariant::Error> =
-        icon_data.fields()[4].try_clone().unwrap().try_into();
-    let _channels: Result<i32, zvariant::Error> =
-        icon_data.fields()[5].try_clone().unwrap().try_into();
-    let data = icon_data.fields()[6].try_clone();
-    let mut vec_val: Vec<u8> = Vec::new();
-    if width.is_err() || height.is_err() || data.is_err() {
+/// Reads field `index` of an image data structure.
+fn image_data_field<'a, T>(icon_data: &Structure<'a>, index: usize) -> Option<T>
+where
+    T: TryFrom<Value<'a>>,
+{
+    icon_data.fields()[index].try_clone().ok()?.try_into().ok()
+}
+
+/// Image data from a notification, converted to RGBA.
+pub struct IconImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Reads image data. Images larger than `max_size` pixels on either side are refused, 0 meaning
+/// no limit. Anything which is not valid `(iiibiiay)` image data with 8 bit RGB or RGBA samples
+/// is refused as well.
+pub fn decode_icon(icon_data: &Structure, max_size: u32) -> Option<IconImage> {
+    if icon_data.fields().len() != 7 {
+        log!(
+            "Dropping image data with {} fields",
+            icon_data.fields().len()
+        );
         return None;
     }
-    let width = width.unwrap();
-    let height = height.unwrap();
-    let data = data.unwrap();
-    match data {
-        Value::Array(data) => {
-            data.iter()
-                .map(|x| vec_val.push(x.try_clone().unwrap().try_into().unwrap()))
-                .count();
-        }
-        _ => {
-            return None;
+    let width: i32 = image_data_field(icon_data, 0)?;
+    let height: i32 = image_data_field(icon_data, 1)?;
+    let rowstride: i32 = image_data_field(icon_data, 2)?;
+    let has_alpha: bool = image_data_field(icon_data, 3)?;
+    let bits_per_sample: i32 = image_data_field(icon_data, 4)?;
+    let channels: i32 = image_data_field(icon_data, 5)?;
+    if width <= 0
+        || height <= 0
+        || (max_size > 0 && (width as u32 > max_size || height as u32 > max_size))
+    {
+        log!("Dropping {}x{} image data", width, height);
+        return None;
+    }
+    if bits_per_sample != 8 || channels != if has_alpha { 4 } else { 3 } {
+        log!(
+            "Dropping image data with {} channels of {} bits",
+            channels,
+            bits_per_sample
+        );
+        return None;
+    }
+    let (width, height, channels) = (width as usize, height as usize, channels as usize);
+    let row_len = width * channels;
+    if rowstride < 0 || (rowstride as usize) < row_len {
+        log!("Dropping image data with rowstride {}", rowstride);
+        return None;
+    }
+    let rowstride = rowstride as usize;
+
+    let data = match icon_data.fields()[6] {
+        Value::Array(ref data) => data,
+        _ => return None,
+    };
+    // The last row does not have to be padded to the full rowstride
+    let min_len = rowstride * (height - 1) + row_len;
+    if data.len() < min_len || data.len() > rowstride * height {
+        log!(
+            "Dropping {}x{} image data of {} bytes",
+            width,
+            height,
+            data.len()
+        );
+        return None;
+    }
+    let data: Vec<u8> = data
+        .iter()
+        .map(|byte| byte.try_clone().ok()?.try_into().ok())
+        .collect::<Option<_>>()?;
+    // Strip the padding and add an alpha channel where there is none
+    let mut vec_val: Vec<u8> = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        let row = &data[row * rowstride..row * rowstride + row_len];
+        if has_alpha {
+            vec_val.extend_from_slice(row);
+        } else {
+            for pixel in row.chunks_exact(3) {
+                vec_val.extend_from_slice(pixel);
+                vec_val.push(u8::MAX);
+            }
         }
     }
+    Some(IconImage {
+        width: width as u32,
+        height: height as u32,
+        rgba: vec_val,
+    })
+}
+
+/// Saves an image to the icon dir. This only happens on the state task, which also prunes the
+/// icon dir, so an icon is never removed between being saved and being referenced.
+pub fn save_icon(image: &IconImage) -> Option<String> {
+    // Name the file after its contents so that identical images are only stored once and
+    // history entries keep pointing at a valid file across restarts
+    let mut hasher = DefaultHasher::new();
+    (image.width, image.height, &image.rgba).hash(&mut hasher);
+    let icon_path = format!("{}/{:016x}.png", get_icon_dir(), hasher.finish());
+    if Path::new(&icon_path).exists() {
+        return Some(icon_path);
+    }
     let res = image::save_buffer(
         &icon_path,
-        &vec_val,
-        width as u32,
-        height as u32,
+        &image.rgba,
+        image.width,
+        image.height,
         image::ColorType::Rgba8,
     );
     if res.is_err() {
@@ -121,6 +200,24 @@ pub fn save_icon(icon_data: &Structure, id: u32) -> Option<String> {
     Some(icon_path)
 }
 
+pub fn get_state_dir() -> String {
+    let xdg_state_home = std::env::var("XDG_STATE_HOME")
+        .unwrap_or_else(|_| format!("{}/.local/state", std::env::var("HOME").unwrap()));
+    let state_dir = format!("{}/end-rs", xdg_state_home);
+    if !Path::new(&state_dir).exists() {
+        fs::create_dir_all(&state_dir).unwrap();
+    }
+    state_dir
+}
+
+pub fn get_icon_dir() -> String {
+    let icon_dir = format!("{}/icons", get_state_dir());
+    if !Path::new(&icon_dir).exists() {
+        fs::create_dir_all(&icon_dir).unwrap();
+    }
+    icon_dir
+}
+
 pub fn log(args: std::fmt::Arguments) {
     let message = format!(
         "[{}] {}",
@@ -143,3 +240,57 @@ macro_rules! log {
         $crate::utils::log(format_args!($($arg)*))
     };
 }
+
+/// Parses durations like `90`, `30s`, `5m`, `1h30m` or `2d`. Bare numbers are seconds.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    if duration.is_empty() {
+        return None;
+    }
+    if let Ok(secs) = duration.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        let secs = number.parse::<u64>().ok()?.checked_mul(unit)?;
+        total = total.checked_add(secs)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+/// Formats a duration the way `parse_duration` reads it, e.g. `1h30m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts = [
+        (secs / 86400, 'd'),
+        (secs / 3600 % 24, 'h'),
+        (secs / 60 % 60, 'm'),
+        (secs % 60, 's'),
+    ];
+    let formatted: String = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+    if formatted.is_empty() {
+        String::from("0s")
+    } else {
+        formatted
+    }
+}